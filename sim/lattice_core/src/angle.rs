@@ -0,0 +1,181 @@
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+
+use crate::node::Nodes;
+
+//-------------------------------------------------------
+// STRUCTS
+//-------------------------------------------------------
+
+/// Three-body bending spring between two bonds that share a node.
+/// Resists any change of the angle between the bonds, giving the lattice shear stiffness
+/// that links alone can't.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AngleLink {
+    /// Node the two bonds share
+    pub vertex: usize,
+    /// Nodes at the far ends of the two bonds
+    pub ends: [usize; 2],
+    /// Bending stiffness, J per rad^2
+    pub stiffness: f32,
+    /// Angle between the bonds when nothing is bent, rad
+    pub rest_angle: f32,
+}
+
+//-------------------------------------------------------
+// IMPLEMENTATIONS
+//-------------------------------------------------------
+
+impl AngleLink {
+    /// Bond pairs within this much of straight or square are joined, rad
+    const ANGLE_TOLERANCE: f32 = 1e-3;
+    /// Below this sine the bonds are taken as straight and the bending direction is unknown
+    const MIN_SINE: f32 = 1e-6;
+
+    /// Angle between the two bonds at the given positions, rad
+    pub fn angle(&self, pos: &[Vec3]) -> f32 {
+        let u = pos[self.ends[0]] - pos[self.vertex];
+        let w = pos[self.ends[1]] - pos[self.vertex];
+        // atan2 keeps its precision near straight where acos doesn't
+        u.cross(w).length().atan2(u.dot(w))
+    }
+
+    /// Bending energy stored at the given positions, J
+    pub fn potential_energy(&self, pos: &[Vec3]) -> f32 {
+        let bend = self.angle(pos) - self.rest_angle;
+        0.5 * self.stiffness * bend * bend
+    }
+
+    /// Force on each end node at the given positions, N. The vertex gets minus their sum.
+    pub fn forces(&self, pos: &[Vec3]) -> [Vec3; 2] {
+        let u = pos[self.ends[0]] - pos[self.vertex];
+        let w = pos[self.ends[1]] - pos[self.vertex];
+        let (u_len, w_len) = (u.length(), w.length());
+        let (u_dir, w_dir) = (u / u_len, w / w_len);
+
+        let cos = u_dir.dot(w_dir);
+        let sin = u_dir.cross(w_dir).length();
+        if sin < Self::MIN_SINE {
+            return [Vec3::ZERO; 2];
+        }
+
+        // Each end is pushed around the vertex in the plane of the bonds, against the bend
+        let torque = self.stiffness * (sin.atan2(cos) - self.rest_angle);
+        [
+            torque * (w_dir - cos * u_dir) / (u_len * sin),
+            torque * (u_dir - cos * w_dir) / (w_len * sin),
+        ]
+    }
+}
+
+/// Join every straight and every square pair of bonds at each node.
+/// `neighbours` holds the nodes bonded to each node.
+pub(crate) fn bending_links(
+    nodes: &Nodes,
+    neighbours: &[Vec<usize>],
+    stiffness: f32,
+) -> Vec<AngleLink> {
+    const STRAIGHT: f32 = std::f32::consts::PI;
+    const SQUARE: f32 = std::f32::consts::FRAC_PI_2;
+
+    let mut angles = Vec::new();
+    for (vertex, bonded) in neighbours.iter().enumerate() {
+        for (first, a) in bonded.iter().enumerate() {
+            for b in bonded[first + 1..].iter() {
+                let mut angle = AngleLink {
+                    vertex,
+                    ends: [*a, *b],
+                    stiffness,
+                    rest_angle: 0.0,
+                };
+                let rest_angle = angle.angle(&nodes.pos);
+                for target in [STRAIGHT, SQUARE] {
+                    if (rest_angle - target).abs() <= AngleLink::ANGLE_TOLERANCE {
+                        angle.rest_angle = rest_angle;
+                        angles.push(angle);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    angles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crystal::LatticeKind;
+    use crate::lattice::{test_params, Lattice};
+    use glam::UVec3;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn forces_are_the_slope_of_the_energy() {
+        let angle = AngleLink {
+            vertex: 0,
+            ends: [1, 2],
+            stiffness: 3.0,
+            rest_angle: 1.2,
+        };
+        let pos = [
+            Vec3::new(0.1, -0.2, 0.0),
+            Vec3::new(1.0, 0.1, 0.2),
+            Vec3::new(-0.3, 0.9, -0.4),
+        ];
+        let [first, second] = angle.forces(&pos);
+        let forces = [-first - second, first, second];
+
+        let step = 1e-3;
+        for node in 0..3 {
+            for axis in 0..3 {
+                let energy_at = |offset: f32| {
+                    let mut moved = pos;
+                    moved[node][axis] += offset;
+                    angle.potential_energy(&moved)
+                };
+                let slope = (energy_at(step) - energy_at(-step)) / (2.0 * step);
+                let force = forces[node][axis];
+                assert!(
+                    (force + slope).abs() < 1e-2 * force.abs().max(1.0),
+                    "node {node} axis {axis}: force {force}, -dU/dx {}",
+                    -slope
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn straight_and_square_bond_pairs_are_joined() {
+        // 3 by 2 by 2 nodes, only the 4 in the middle of the long axis have a straight pair
+        let mut params = test_params(UVec3::new(2, 1, 1), LatticeKind::SimpleCubic);
+        params.bond_shells = vec![50.0];
+        params.bending_stiffness = 2.0;
+        let lattice = Lattice::generate(&params, &mut StdRng::seed_from_u64(1));
+        let pos = &lattice.nodes.pos;
+
+        let near = |angle: f32, target: f32| (angle - target).abs() < 1e-3;
+        let straight = lattice
+            .angles
+            .iter()
+            .filter(|angle| near(angle.rest_angle, std::f32::consts::PI))
+            .count();
+        let square = lattice
+            .angles
+            .iter()
+            .filter(|angle| near(angle.rest_angle, std::f32::consts::FRAC_PI_2))
+            .count();
+        // The 8 end nodes have 3 square pairs each, the 4 middle ones 5
+        assert_eq!(straight, 4);
+        assert_eq!(square, 8 * 3 + 4 * 5);
+        assert_eq!(lattice.angles.len(), straight + square);
+
+        for angle in lattice.angles.iter() {
+            assert_eq!(angle.stiffness, 2.0);
+            assert!(near(angle.angle(pos), angle.rest_angle));
+            for end in angle.ends {
+                assert!(lattice.links.joins(angle.vertex, end));
+            }
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use glam::{BVec3, UVec3, Vec3};
+use serde::{Deserialize, Serialize};
+
+//-------------------------------------------------------
+// STRUCTS
+//-------------------------------------------------------
+
+/// One of the six faces of the lattice
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Face {
+    MinX,
+    MaxX,
+    MinY,
+    MaxY,
+    MinZ,
+    MaxZ,
+}
+
+/// Which nodes a boundary condition applies to
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeSelector {
+    /// The 8 corners of the lattice
+    Corners,
+    /// Every node on a face
+    Face(Face),
+    /// Every node on the edge where two faces meet
+    Edge(Face, Face),
+    /// Every node on the plane normal to the axis (0 = x, 1 = y, 2 = z)
+    /// at the given position, measured in unit cells from the origin
+    Plane { axis: usize, position: f32 },
+    /// Any node the function returns true for given the xyz index of its cell and its basis index.
+    /// Can only be set in code, through the boundary conditions of `LatticeParams`.
+    #[serde(skip)]
+    Predicate(fn(UVec3, usize) -> bool),
+}
+
+/// Restricts the motion of a node to a subspace. Nodes that can't move at all use `Static` instead.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Constraint {
+    /// The components set to true are held fixed, the rest are free
+    FixedAxes(BVec3),
+    /// The node can only slide in the plane with the given normal
+    Roller { normal: Vec3 },
+}
+
+/// How the selected nodes are held
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Anchor {
+    /// The nodes can't move at all
+    Fixed,
+    /// Only some of the motion of the nodes is restricted
+    Partial(Constraint),
+}
+
+/// Anchors every node picked by the selector
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BoundaryCondition {
+    pub nodes: NodeSelector,
+    pub anchor: Anchor,
+}
+
+/// The boundary conditions applied when the lattice is generated.
+/// When more than one condition selects a node, the first one in the list is used.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct BoundaryConditions(pub Vec<BoundaryCondition>);
+
+//-------------------------------------------------------
+// IMPLEMENTATIONS
+//-------------------------------------------------------
+
+impl Face {
+    /// Axis normal to the face, 0 = x, 1 = y, 2 = z
+    pub fn axis(&self) -> usize {
+        match self {
+            Face::MinX | Face::MaxX => 0,
+            Face::MinY | Face::MaxY => 1,
+            Face::MinZ | Face::MaxZ => 2,
+        }
+    }
+
+    /// Position of the face along its axis in unit cells
+    pub fn position(&self, lattice_dims: UVec3) -> f32 {
+        match self {
+            Face::MinX | Face::MinY | Face::MinZ => 0.0,
+            Face::MaxX | Face::MaxY | Face::MaxZ => lattice_dims[self.axis()] as f32,
+        }
+    }
+
+    /// Check if a site lies on the face given its fractional coordinates
+    fn contains(&self, frac: Vec3, lattice_dims: UVec3) -> bool {
+        on_plane(frac, self.axis(), self.position(lattice_dims))
+    }
+}
+
+impl NodeSelector {
+    /// Check if a site is selected given the xyz index of its cell, its basis index and
+    /// its fractional coordinates in the lattice
+    pub fn selects(&self, cell: UVec3, basis: usize, frac: Vec3, lattice_dims: UVec3) -> bool {
+        match self {
+            NodeSelector::Corners => (0..3).all(|axis| {
+                on_plane(frac, axis, 0.0) || on_plane(frac, axis, lattice_dims[axis] as f32)
+            }),
+            NodeSelector::Face(face) => face.contains(frac, lattice_dims),
+            NodeSelector::Edge(face_a, face_b) => {
+                face_a.contains(frac, lattice_dims) && face_b.contains(frac, lattice_dims)
+            }
+            NodeSelector::Plane { axis, position } => on_plane(frac, *axis, *position),
+            NodeSelector::Predicate(predicate) => predicate(cell, basis),
+        }
+    }
+}
+
+impl Constraint {
+    /// Remove the parts of a force or velocity the constraint does not allow
+    pub fn project(&self, vec: Vec3) -> Vec3 {
+        match self {
+            Constraint::FixedAxes(fixed) => Vec3::select(*fixed, Vec3::ZERO, vec),
+            Constraint::Roller { normal } => {
+                let normal = normal.normalize();
+                vec - vec.dot(normal) * normal
+            }
+        }
+    }
+
+    /// Number of directions the node is still free to move in
+    pub fn degrees_of_freedom(&self) -> u32 {
+        match self {
+            Constraint::FixedAxes(fixed) => 3 - fixed.bitmask().count_ones(),
+            Constraint::Roller { .. } => 2,
+        }
+    }
+}
+
+impl BoundaryConditions {
+    /// Get the anchor of a site, none if no condition selects it
+    pub fn anchor(
+        &self,
+        cell: UVec3,
+        basis: usize,
+        frac: Vec3,
+        lattice_dims: UVec3,
+    ) -> Option<Anchor> {
+        self.0
+            .iter()
+            .find(|condition| condition.nodes.selects(cell, basis, frac, lattice_dims))
+            .map(|condition| condition.anchor)
+    }
+}
+
+/// Check if the fractional coordinates lie on the plane normal to the axis at the given position
+fn on_plane(frac: Vec3, axis: usize, position: f32) -> bool {
+    const TOLERANCE: f32 = 1e-4;
+    (frac[axis] - position).abs() <= TOLERANCE
+}
@@ -0,0 +1,151 @@
+use crate::node::Links;
+
+//-------------------------------------------------------
+// STRUCTS
+//-------------------------------------------------------
+
+/// Links split into colour classes where no two links of a class share a node.
+/// The links of a class can have their forces applied at the same time without two
+/// threads ever writing the same node, and every node gets its forces added in class
+/// order no matter how many threads are used.
+#[derive(Clone, Debug, Default)]
+pub struct LinkColoring {
+    classes: Vec<Vec<ColoredLink>>,
+}
+
+/// A link in a colour class
+#[derive(Clone, Copy, Debug)]
+pub struct ColoredLink {
+    /// Index of the node the link comes from
+    pub from: u32,
+    /// Position of the link in the link buffers
+    pub link_idx: u32,
+}
+
+/// Lets several threads write into a slice at once.
+/// Only sound when no two threads touch the same index at the same time.
+pub(crate) struct SharedSlice<T> {
+    ptr: *mut T,
+    len: usize,
+}
+
+//-------------------------------------------------------
+// IMPLEMENTATIONS
+//-------------------------------------------------------
+
+impl LinkColoring {
+    /// Greedily give every link the first colour neither of its nodes has used yet.
+    /// Links are visited in the order they are stored so the coloring is always the same.
+    /// Greedy edge coloring can need up to 2Δ - 1 colours where Δ is the most links on any
+    /// one node, so the colours used by each node are kept in a bitset that grows as needed.
+    pub fn new(links: &Links, num_nodes: usize) -> Self {
+        // Bit c % 64 of word c / 64 is set when the node already has a link of colour c
+        let mut used: Vec<Vec<u64>> = vec![Vec::new(); num_nodes];
+        let mut classes: Vec<Vec<ColoredLink>> = Vec::new();
+
+        for from in 0..num_nodes {
+            for link_idx in links.row(from) {
+                let to = links.to(link_idx);
+                let color = first_free_color(&used[from], &used[to]);
+                for node in [from, to] {
+                    let word = color / u64::BITS as usize;
+                    if word >= used[node].len() {
+                        used[node].resize(word + 1, 0);
+                    }
+                    used[node][word] |= 1 << (color % u64::BITS as usize);
+                }
+
+                if color >= classes.len() {
+                    classes.resize_with(color + 1, Vec::new);
+                }
+                classes[color].push(ColoredLink {
+                    from: from as u32,
+                    link_idx: link_idx as u32,
+                });
+            }
+        }
+
+        LinkColoring { classes }
+    }
+
+    /// Every colour class in order
+    pub fn classes(&self) -> &[Vec<ColoredLink>] {
+        &self.classes
+    }
+}
+
+impl<T> SharedSlice<T> {
+    pub fn new(slice: &mut [T]) -> Self {
+        SharedSlice {
+            ptr: slice.as_mut_ptr(),
+            len: slice.len(),
+        }
+    }
+
+    /// Get the element at the index.
+    ///
+    /// # Safety
+    /// No other reference to the element may exist while the returned one is alive.
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn get_mut(&self, idx: usize) -> &mut T {
+        assert!(idx < self.len);
+        // SAFETY: the index was just checked against the slice the pointer came from, and the
+        // caller promises the element isn't aliased
+        unsafe { &mut *self.ptr.add(idx) }
+    }
+}
+
+/// Lowest colour missing from both bitsets, words past the end of a bitset are empty
+fn first_free_color(a: &[u64], b: &[u64]) -> usize {
+    let mut word = 0;
+    loop {
+        let taken = a.get(word).copied().unwrap_or(0) | b.get(word).copied().unwrap_or(0);
+        if taken != u64::MAX {
+            return word * u64::BITS as usize + (!taken).trailing_zeros() as usize;
+        }
+        word += 1;
+    }
+}
+
+// SAFETY: the elements are only reached through get_mut, and the link evaluation only calls it
+// from several threads for the links of one colour class at a time. The coloring never gives two
+// links sharing a node the same colour and puts every link in exactly one class, so the threads
+// write disjoint nodes and link slots.
+unsafe impl<T: Send> Sync for SharedSlice<T> {}
+// SAFETY: as for Sync, sending the slice to another thread only lets it write its own elements
+unsafe impl<T: Send> Send for SharedSlice<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::Link;
+
+    #[test]
+    fn classes_never_share_a_node() {
+        // Every node of a complete graph of 70 nodes has 69 links, more colours than fit in a word
+        let num_nodes = 70;
+        let link = Link::new(1.0, Default::default(), 0.0, None, 1.0);
+        let pairs = (0..num_nodes)
+            .flat_map(|from| (from + 1..num_nodes).map(move |to| (from, to)))
+            .map(|(from, to)| (from, to, link.clone()))
+            .collect();
+        let links = Links::new(num_nodes, pairs);
+        let coloring = LinkColoring::new(&links, num_nodes);
+
+        assert!(coloring.classes().len() > u64::BITS as usize);
+        let mut seen = vec![false; links.len()];
+        for class in coloring.classes() {
+            let mut touched = vec![false; num_nodes];
+            for colored in class {
+                let link_idx = colored.link_idx as usize;
+                assert!(!seen[link_idx], "link {link_idx} is in two classes");
+                seen[link_idx] = true;
+                for node in [colored.from as usize, links.to(link_idx)] {
+                    assert!(!touched[node], "node {node} has two links of a class");
+                    touched[node] = true;
+                }
+            }
+        }
+        assert!(seen.iter().all(|seen| *seen));
+    }
+}
@@ -0,0 +1,197 @@
+use glam::{IVec3, UVec3, Vec3};
+use serde::{Deserialize, Serialize};
+
+//-------------------------------------------------------
+// STRUCTS
+//-------------------------------------------------------
+
+/// Crystal structure the lattice is built from.
+/// All cubic kinds use a conventional cell with an edge of the lattice's cell length.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LatticeKind {
+    #[default]
+    SimpleCubic,
+    BodyCenteredCubic,
+    FaceCenteredCubic,
+    /// Hexagonal close packed with the ideal c/a ratio, c axis along z
+    HexagonalClosePacked,
+    Diamond,
+    RockSalt,
+}
+
+/// Lattice vectors plus the basis sites of one unit cell
+pub struct UnitCell {
+    /// Lattice vectors, a cell at index (x, y, z) sits at x*a + y*b + z*c
+    pub vectors: [Vec3; 3],
+    /// Fractional coordinates of every site in the cell
+    pub basis: Vec<Vec3>,
+}
+
+/// A bond from a basis site to the site `basis` in the cell offset by `cell_offset`
+#[derive(Clone, Copy, Debug)]
+pub struct BondOffset {
+    pub cell_offset: IVec3,
+    pub basis: usize,
+    /// Neighbour shell of the bond, 0 for the nearest neighbours
+    pub shell: usize,
+}
+
+//-------------------------------------------------------
+// IMPLEMENTATIONS
+//-------------------------------------------------------
+
+impl LatticeKind {
+    /// Get the unit cell of the structure with the given conventional cell edge length
+    pub fn unit_cell(&self, cell_len: f32) -> UnitCell {
+        let cubic = [Vec3::X * cell_len, Vec3::Y * cell_len, Vec3::Z * cell_len];
+        let fcc_basis = vec![
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.5, 0.5, 0.0),
+            Vec3::new(0.5, 0.0, 0.5),
+            Vec3::new(0.0, 0.5, 0.5),
+        ];
+
+        match self {
+            LatticeKind::SimpleCubic => UnitCell {
+                vectors: cubic,
+                basis: vec![Vec3::ZERO],
+            },
+            LatticeKind::BodyCenteredCubic => UnitCell {
+                vectors: cubic,
+                basis: vec![Vec3::ZERO, Vec3::splat(0.5)],
+            },
+            LatticeKind::FaceCenteredCubic => UnitCell {
+                vectors: cubic,
+                basis: fcc_basis,
+            },
+            LatticeKind::HexagonalClosePacked => UnitCell {
+                vectors: [
+                    Vec3::new(cell_len, 0.0, 0.0),
+                    Vec3::new(0.5 * cell_len, 0.5 * f32::sqrt(3.0) * cell_len, 0.0),
+                    Vec3::new(0.0, 0.0, f32::sqrt(8.0 / 3.0) * cell_len),
+                ],
+                basis: vec![Vec3::ZERO, Vec3::new(1.0 / 3.0, 1.0 / 3.0, 0.5)],
+            },
+            LatticeKind::Diamond => UnitCell {
+                vectors: cubic,
+                // two fcc lattices offset by a quarter of the body diagonal
+                basis: fcc_basis
+                    .iter()
+                    .copied()
+                    .chain(fcc_basis.iter().map(|site| *site + Vec3::splat(0.25)))
+                    .collect(),
+            },
+            LatticeKind::RockSalt => UnitCell {
+                vectors: cubic,
+                // two fcc lattices offset by half a cell edge, one for each species
+                basis: fcc_basis
+                    .iter()
+                    .copied()
+                    .chain([
+                        Vec3::new(0.5, 0.0, 0.0),
+                        Vec3::new(0.0, 0.5, 0.0),
+                        Vec3::new(0.0, 0.0, 0.5),
+                        Vec3::new(0.5, 0.5, 0.5),
+                    ])
+                    .collect(),
+            },
+        }
+    }
+}
+
+impl UnitCell {
+    /// Fractional coordinates of a site are allowed to be this far past the lattice edge
+    const EDGE_TOLERANCE: f32 = 1e-4;
+    /// Relative tolerance for two bond lengths to count as the same shell
+    const SHELL_TOLERANCE: f32 = 1e-3;
+
+    /// Get the fractional coordinates of a site in the lattice, measured in unit cells
+    pub fn fractional(&self, cell: IVec3, basis: usize) -> Vec3 {
+        cell.as_vec3() + self.basis[basis]
+    }
+
+    /// Get the position of a site given the index of its cell and its basis index
+    pub fn position(&self, cell: IVec3, basis: usize) -> Vec3 {
+        let frac = self.fractional(cell, basis);
+        frac.x * self.vectors[0] + frac.y * self.vectors[1] + frac.z * self.vectors[2]
+    }
+
+    /// Check if a site lies inside a lattice that is `lattice_dims` cells along each axis.
+    /// Cells past the far edge still contribute the sites sitting on the edge.
+    pub fn site_in_bounds(&self, cell: IVec3, basis: usize, lattice_dims: UVec3) -> bool {
+        let frac = self.fractional(cell, basis);
+        let max = lattice_dims.as_vec3() + Vec3::splat(Self::EDGE_TOLERANCE);
+        frac.min_element() >= 0.0 && frac.cmple(max).all()
+    }
+
+    /// Get the bonds of every basis site out to the given number of neighbour shells.
+    /// Every direction of each shell is included but only one direction of each bond is returned so that walking every site and
+    /// creating its bonds never creates a bond twice.
+    pub fn bond_offsets(&self, num_shells: usize) -> Vec<Vec<BondOffset>> {
+        const SEARCH: i32 = 2;
+
+        let mut bonds = Vec::with_capacity(self.basis.len());
+        for from_basis in 0..self.basis.len() {
+            let from_pos = self.position(IVec3::ZERO, from_basis);
+
+            // Every neighbour within the search range along with its distance
+            let mut neighbours = Vec::new();
+            for z in -SEARCH..=SEARCH {
+                for y in -SEARCH..=SEARCH {
+                    for x in -SEARCH..=SEARCH {
+                        for to_basis in 0..self.basis.len() {
+                            let cell_offset = IVec3::new(x, y, z);
+                            if cell_offset == IVec3::ZERO && to_basis == from_basis {
+                                continue;
+                            }
+                            let dist = from_pos.distance(self.position(cell_offset, to_basis));
+                            neighbours.push((dist, cell_offset, to_basis));
+                        }
+                    }
+                }
+            }
+
+            // The shells are the distinct neighbour distances in increasing order
+            let mut shells: Vec<f32> = Vec::new();
+            neighbours.sort_by(|a, b| a.0.total_cmp(&b.0));
+            for (dist, _, _) in neighbours.iter() {
+                match shells.last() {
+                    Some(last) if (dist - last) <= Self::SHELL_TOLERANCE * last => {}
+                    _ => shells.push(*dist),
+                }
+            }
+            assert!(
+                num_shells <= shells.len(),
+                "only {} neighbour shells are within reach",
+                shells.len()
+            );
+            let shell_of = |dist: f32| {
+                shells
+                    .iter()
+                    .position(|shell| (dist - shell).abs() <= Self::SHELL_TOLERANCE * shell)
+            };
+
+            // Keep one direction of each bond, the reverse bond is seen from the other site
+            bonds.push(
+                neighbours
+                    .iter()
+                    .filter(|(_, cell_offset, to_basis)| {
+                        (cell_offset.z, cell_offset.y, cell_offset.x, *to_basis)
+                            > (0, 0, 0, from_basis)
+                    })
+                    .filter_map(|(dist, cell_offset, to_basis)| {
+                        let shell = shell_of(*dist).filter(|shell| *shell < num_shells)?;
+                        Some(BondOffset {
+                            cell_offset: *cell_offset,
+                            basis: *to_basis,
+                            shell,
+                        })
+                    })
+                    .collect(),
+            );
+        }
+
+        bonds
+    }
+}
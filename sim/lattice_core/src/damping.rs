@@ -0,0 +1,52 @@
+use serde::Deserialize;
+
+//-------------------------------------------------------
+// STRUCTS
+//-------------------------------------------------------
+
+/// How energy is removed from the lattice.
+/// Select it through the lattice config.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DampingModel {
+    /// No dissipation at all, useful for checking energy conservation.
+    None,
+    /// Dashpot on every link acting on the relative velocity of its two nodes along the
+    /// bond axis. Uses each link's own damping coefficient.
+    #[default]
+    BondDashpot,
+    /// Rayleigh damping, C = mass_coeff * M + stiffness_coeff * K.
+    /// The mass term drags each node, the stiffness term is a dashpot on each link.
+    Rayleigh {
+        mass_coeff: f32,
+        stiffness_coeff: f32,
+    },
+    /// Drag against the absolute velocity of each node, f = -coeff * v.
+    ViscousDrag { coeff: f32 },
+}
+
+//-------------------------------------------------------
+// IMPLEMENTATIONS
+//-------------------------------------------------------
+
+impl DampingModel {
+    /// Dashpot coefficient to apply along a link with the given constants
+    pub fn link_coeff(&self, spring_const: f32, link_damping_coeff: f32) -> f32 {
+        match *self {
+            DampingModel::BondDashpot => link_damping_coeff,
+            DampingModel::Rayleigh {
+                stiffness_coeff, ..
+            } => stiffness_coeff * spring_const,
+            DampingModel::None | DampingModel::ViscousDrag { .. } => 0.0,
+        }
+    }
+
+    /// Drag coefficient to apply against the velocity of a node with the given mass
+    pub fn node_coeff(&self, mass: f32) -> f32 {
+        match *self {
+            DampingModel::Rayleigh { mass_coeff, .. } => mass_coeff * mass,
+            DampingModel::ViscousDrag { coeff } => coeff,
+            DampingModel::None | DampingModel::BondDashpot => 0.0,
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use glam::Vec3;
+use serde::Deserialize;
+
+//-------------------------------------------------------
+// STRUCTS
+//-------------------------------------------------------
+
+/// How the ground pushes back on a node that reaches it.
+/// Select it through the ground config.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContactModel {
+    /// After each step a node below the ground is put back on it, its downward velocity is
+    /// reflected by the restitution and friction takes the matching impulse off its sliding.
+    #[default]
+    Impulse,
+    /// Spring force of stiffness, N/m, on how far a node is below the ground plus a dashpot
+    /// set from the restitution. Friction acts as a force alongside it.
+    Penalty { stiffness: f32 },
+}
+
+/// Horizontal floor the nodes can't fall through, the y axis is up
+#[derive(Clone, Copy, Debug)]
+pub struct Ground {
+    /// Height of the floor, m. Nodes touch it when their centre reaches it.
+    pub height: f32,
+    pub contact: ContactModel,
+    /// Fraction of the speed into the ground a node bounces back with, 0 to 1
+    pub restitution: f32,
+    /// Coulomb friction coefficient, the sliding force is at most this times the normal force
+    pub friction: f32,
+}
+
+/// Force of a penalty contact on a node
+pub(crate) struct ContactForce {
+    /// N
+    pub force: Vec3,
+    /// Power the contact's dashpot and friction are removing, W
+    pub dissipated_power: f32,
+}
+
+//-------------------------------------------------------
+// IMPLEMENTATIONS
+//-------------------------------------------------------
+
+impl Ground {
+    /// Below this sliding speed the friction force ramps down to 0 instead of flipping
+    /// direction every evaluation, m/s. The ramp acts as a drag of friction * normal / speed,
+    /// much slower than this and it is too stiff for the explicit integrators at usual time steps.
+    const STICK_SPEED: f32 = 5e-2;
+
+    /// Force of a penalty contact on a node, none if it isn't touching or the contact uses impulses
+    pub(crate) fn penalty_force(&self, pos: Vec3, vel: Vec3, mass: f32) -> Option<ContactForce> {
+        let ContactModel::Penalty { stiffness } = self.contact else {
+            return None;
+        };
+        let depth = self.height - pos.y;
+        if depth <= 0.0 {
+            return None;
+        }
+
+        // Damping ratio of a spring and dashpot that bounces back with the restitution
+        let log_restitution = self.restitution.max(f32::EPSILON).ln();
+        let damping_ratio = -log_restitution
+            / (std::f32::consts::PI * std::f32::consts::PI + log_restitution * log_restitution)
+                .sqrt();
+        let damping_coeff = 2.0 * damping_ratio * (stiffness * mass).sqrt();
+
+        // The ground only pushes, never pulls
+        let normal = (stiffness * depth - damping_coeff * vel.y).max(0.0);
+        let sliding = Vec3::new(vel.x, 0.0, vel.z);
+        let friction = -self.friction * normal * sliding / sliding.length().max(Self::STICK_SPEED);
+
+        // Whatever the normal force doesn't give back of the spring, plus all of the friction
+        let dissipated_power = -(normal - stiffness * depth) * vel.y - friction.dot(sliding);
+        Some(ContactForce {
+            force: normal * Vec3::Y + friction,
+            dissipated_power,
+        })
+    }
+
+    /// Elastic energy stored in a penalty contact, J
+    pub(crate) fn potential_energy(&self, pos: Vec3) -> f32 {
+        match self.contact {
+            ContactModel::Penalty { stiffness } => {
+                let depth = (self.height - pos.y).max(0.0);
+                0.5 * stiffness * depth * depth
+            }
+            ContactModel::Impulse => 0.0,
+        }
+    }
+
+    /// Put a node that went below the ground back on it and apply the impulse of the contact.
+    /// Does nothing for penalty contacts. Returns the kinetic energy the impulse removed, J,
+    /// the potential energy gained by moving the node is left to the caller.
+    pub(crate) fn resolve_impulse(&self, pos: &mut Vec3, vel: &mut Vec3, mass: f32) -> f32 {
+        if self.contact != ContactModel::Impulse || pos.y >= self.height {
+            return 0.0;
+        }
+        pos.y = self.height;
+        if vel.y >= 0.0 {
+            return 0.0;
+        }
+
+        let before = 0.5 * mass * vel.length_squared();
+        // Friction impulse is at most friction times the normal impulse, enough to stop the sliding
+        let normal_change = (1.0 + self.restitution) * -vel.y;
+        let sliding = Vec3::new(vel.x, 0.0, vel.z);
+        let sliding_speed = sliding.length();
+        if sliding_speed > 0.0 {
+            let slowdown = (self.friction * normal_change).min(sliding_speed);
+            *vel -= slowdown * sliding / sliding_speed;
+        }
+        vel.y *= -self.restitution;
+        before - 0.5 * mass * vel.length_squared()
+    }
+}
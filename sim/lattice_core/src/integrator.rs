@@ -0,0 +1,43 @@
+use glam::Vec3;
+use serde::Deserialize;
+
+//-------------------------------------------------------
+// STRUCTS
+//-------------------------------------------------------
+
+/// Time integration scheme used to advance the nodes every fixed step.
+/// Select it through the lattice config.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Integrator {
+    /// v += a*dt then x += v*dt. First order, one force evaluation per step.
+    #[default]
+    SemiImplicitEuler,
+    /// Kick-drift-kick. Second order and symplectic, two force evaluations per step.
+    VelocityVerlet,
+    /// Drift-kick-drift (position Verlet). Second order and symplectic, one force evaluation per step.
+    Leapfrog,
+    /// Classic fourth order Runge-Kutta. Not symplectic, four force evaluations per step.
+    RungeKutta4,
+}
+
+/// Position and velocity of a node at the start of a step along with the
+/// weighted sum of the stage derivatives. Only used by multi-stage schemes.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct StageState {
+    pub pos: Vec3,
+    pub vel: Vec3,
+    pub sum_dpos: Vec3,
+    pub sum_dvel: Vec3,
+}
+
+//-------------------------------------------------------
+// IMPLEMENTATIONS
+//-------------------------------------------------------
+
+impl Integrator {
+    /// Weights applied to each stage derivative of RK4
+    pub const RK4_WEIGHTS: [f32; 4] = [1.0, 2.0, 2.0, 1.0];
+    /// Fraction of the time step at which the next RK4 stage is evaluated
+    pub const RK4_STAGE_OFFSETS: [f32; 3] = [0.5, 0.5, 1.0];
+}
@@ -0,0 +1,508 @@
+use glam::{IVec3, UVec3, Vec3};
+use rand::{distributions::Uniform, Rng};
+
+use crate::angle::{bending_links, AngleLink};
+use crate::boundary::{Anchor, BoundaryConditions};
+use crate::crystal::{LatticeKind, UnitCell};
+use crate::node::{Link, Links, Nodes};
+use crate::potential::LinkPotential;
+use crate::velocity::{maxwell_boltzmann, thermalize, VelocityInit};
+
+//-------------------------------------------------------
+// STRUCTS
+//-------------------------------------------------------
+
+/// Everything needed to build a lattice
+#[derive(Clone, Debug)]
+pub struct LatticeParams {
+    /// Number of unit cells along x, y and z
+    pub dims: UVec3,
+    pub kind: LatticeKind,
+    /// Edge length of the conventional unit cell, m
+    pub cell_len: f32,
+    pub node_mass: f32,
+    /// Spring constant of the links of each neighbour shell that is bonded, nearest first.
+    /// Holds 1 to `MAX_BOND_SHELLS` shells.
+    pub bond_shells: Vec<f32>,
+    /// Stiffness of the angle links joining straight and square pairs of nearest neighbour bonds,
+    /// J per rad^2. None are created when it is 0.
+    pub bending_stiffness: f32,
+    pub link_potential: LinkPotential,
+    pub damping_coeff: f32,
+    /// Strain past which links break, none for links that never break
+    pub breaking_strain: Option<f32>,
+    pub boundary_conditions: BoundaryConditions,
+    /// How the starting velocities are drawn
+    pub velocity_init: VelocityInit,
+    /// Every velocity component starts uniformly distributed in this range with the uniform velocity init
+    pub start_vel_min: f32,
+    pub start_vel_max: f32,
+}
+
+/// All of the nodes and links of the lattice.
+/// Nodes are indexed by the xyz index of their unit cell plus the index of their site in the cell's basis.
+pub struct Lattice {
+    /// Number of unit cells along each axis
+    pub dims: UVec3,
+    /// Dims are the number of cells along each axis plus one, NOT the number of unit cells along each axis.
+    /// For the simple cubic lattice these are the number of nodes along each axis.
+    pub nodes_dims: UVec3,
+    /// Crystal structure of the lattice
+    pub kind: LatticeKind,
+    /// Edge length of the conventional unit cell, m
+    pub cell_len: f32,
+    /// Unit cell of the crystal structure
+    pub unit_cell: UnitCell,
+    /// Every node in the order it was generated
+    pub nodes: Nodes,
+    /// Every intact link
+    pub links: Links,
+    /// Every angle link, only between intact links
+    pub angles: Vec<AngleLink>,
+    /// Position of every lattice site's node in nodes, none if the site lies outside the lattice
+    sites: Vec<Option<usize>>,
+}
+
+/// Most neighbour shells that can be bonded
+pub const MAX_BOND_SHELLS: usize = 3;
+
+//-------------------------------------------------------
+// IMPLEMENTATIONS
+//-------------------------------------------------------
+
+impl Lattice {
+    /// Create an empty lattice for the given dimensions and crystal structure.
+    /// Dimensions are the number of unit cells along each axis.
+    pub fn new(lattice_dims: UVec3, kind: LatticeKind, cell_len: f32) -> Self {
+        let unit_cell = kind.unit_cell(cell_len);
+        // all internal math is based on num cells along each axis so dims + 1
+        let nodes_dims = lattice_dims + UVec3::ONE;
+        let num_sites = nodes_dims.element_product() as usize * unit_cell.basis.len();
+        Self {
+            dims: lattice_dims,
+            nodes_dims,
+            kind,
+            cell_len,
+            unit_cell,
+            nodes: Nodes::with_capacity(calc_num_nodes(lattice_dims, kind) as usize),
+            links: Links::new(0, Vec::new()),
+            angles: Vec::new(),
+            sites: vec![None; num_sites],
+        }
+    }
+
+    /// Build the nodes and links of a lattice. The starting velocities are drawn from rng.
+    pub fn generate(params: &LatticeParams, rng: &mut impl Rng) -> Self {
+        let mut lattice = Lattice::new(params.dims, params.kind, params.cell_len);
+        lattice.create_all_nodes(params, rng);
+        lattice.generate_links(params);
+        lattice
+    }
+
+    /// Put a lattice back together from nodes, links and angle links that were taken from one with the same
+    /// dimensions, crystal structure and cell length. The nodes must be in the order they were generated.
+    pub fn from_parts(
+        lattice_dims: UVec3,
+        kind: LatticeKind,
+        cell_len: f32,
+        nodes: Nodes,
+        links: Links,
+        angles: Vec<AngleLink>,
+    ) -> Self {
+        let mut lattice = Lattice::new(lattice_dims, kind, cell_len);
+        assert_eq!(
+            calc_num_nodes(lattice_dims, kind) as usize,
+            nodes.len(),
+            "number of nodes doesn't match the lattice"
+        );
+        // Nodes are generated in site order so the sites can be filled back in the same way
+        for (node, (cell, basis)) in lattice
+            .sites_in_order()
+            .collect::<Vec<_>>()
+            .into_iter()
+            .enumerate()
+        {
+            lattice.add(cell, basis, node);
+        }
+        lattice.nodes = nodes;
+        lattice.links = links;
+        lattice.angles = angles;
+        lattice
+    }
+
+    /// Add every node of the lattice
+    fn create_all_nodes(&mut self, params: &LatticeParams, rng: &mut impl Rng) {
+        let dist: Uniform<f32> = Uniform::new_inclusive(params.start_vel_min, params.start_vel_max);
+
+        for (cell, basis) in self.sites_in_order().collect::<Vec<_>>() {
+            let starting_pos = self.unit_cell.position(cell.as_ivec3(), basis);
+            let starting_vel = match params.velocity_init {
+                VelocityInit::Uniform => {
+                    Vec3::new(rng.sample(dist), rng.sample(dist), rng.sample(dist))
+                }
+                VelocityInit::MaxwellBoltzmann { temperature } => {
+                    maxwell_boltzmann(rng, temperature, params.node_mass)
+                }
+            };
+
+            // Anchored nodes shouldn't carry a velocity they can't have into the damping
+            let frac = self.unit_cell.fractional(cell.as_ivec3(), basis);
+            let anchor = params
+                .boundary_conditions
+                .anchor(cell, basis, frac, self.dims);
+            let vel = match anchor {
+                Some(Anchor::Fixed) => Vec3::ZERO,
+                Some(Anchor::Partial(constraint)) => constraint.project(starting_vel),
+                None => starting_vel,
+            };
+
+            let node = self.nodes.push(starting_pos, vel, params.node_mass, anchor);
+            self.add(cell, basis, node);
+        }
+
+        if let VelocityInit::MaxwellBoltzmann { temperature } = params.velocity_init {
+            thermalize(&mut self.nodes, temperature);
+        }
+
+        debug_assert_eq!(
+            calc_num_nodes(self.dims, self.kind) as usize,
+            self.nodes.len()
+        );
+    }
+
+    /// Every site inside the lattice as (cell, basis) in the order their nodes are generated
+    fn sites_in_order(&self) -> impl Iterator<Item = (UVec3, usize)> + '_ {
+        let num_basis = self.unit_cell.basis.len();
+        cells(self.dims)
+            .flat_map(move |cell| (0..num_basis).map(move |basis| (cell, basis)))
+            // Sites of the cells on the far edges can be outside the lattice
+            .filter(|(cell, basis)| self.unit_cell.site_in_bounds(*cell, *basis, self.dims))
+            .map(|(cell, basis)| (cell.as_uvec3(), basis))
+    }
+
+    /// Add a link to every neighbour of every node, and the angle links between the nearest neighbour bonds
+    fn generate_links(&mut self, params: &LatticeParams) {
+        // Turns out, you don't need all the directions cause you
+        // are only constructing the lattice in one direction.
+        // This gets rid of the duplication problem.
+        let num_shells = params.bond_shells.len();
+        let bond_offsets = self.unit_cell.bond_offsets(num_shells);
+        let nodes_dims = self.nodes_dims.as_ivec3();
+        let mut links =
+            Vec::with_capacity(calc_num_links(self.dims, self.kind, num_shells) as usize);
+        // Nearest neighbours of every node, the angle links join their bonds
+        let mut neighbours = vec![Vec::new(); self.nodes.len()];
+
+        for z in 0..nodes_dims.z {
+            for y in 0..nodes_dims.y {
+                for x in 0..nodes_dims.x {
+                    for (basis, offsets) in bond_offsets.iter().enumerate() {
+                        let curr_cell = IVec3 { x, y, z };
+                        let Some(from_node) = self.get_site(curr_cell.as_uvec3(), basis) else {
+                            continue;
+                        };
+
+                        for offset in offsets {
+                            let to_cell = curr_cell + offset.cell_offset;
+
+                            // Check if we are out of bounds
+                            if link_out_of_bounds(to_cell, nodes_dims) {
+                                continue;
+                            }
+                            let Some(to_node) = self.get_site(to_cell.as_uvec3(), offset.basis)
+                            else {
+                                continue;
+                            };
+
+                            // Diagonal springs will not be the same starting length as horizontal and vertical ones
+                            let length =
+                                self.nodes.pos[from_node].distance(self.nodes.pos[to_node]);
+
+                            let link = Link::new(
+                                params.bond_shells[offset.shell],
+                                params.link_potential,
+                                params.damping_coeff,
+                                params.breaking_strain,
+                                length,
+                            );
+                            links.push((from_node, to_node, link));
+                            if offset.shell == 0 {
+                                neighbours[from_node].push(to_node);
+                                neighbours[to_node].push(from_node);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        debug_assert_eq!(
+            calc_num_links(self.dims, self.kind, num_shells) as usize,
+            links.len()
+        );
+        self.links = Links::new(self.nodes.len(), links);
+        if params.bending_stiffness > 0.0 {
+            self.angles = bending_links(&self.nodes, &neighbours, params.bending_stiffness);
+        }
+    }
+
+    /// Get the index of the cell from the array given xyz index in the lattice.
+    pub fn get_data_idx(&self, x: u32, y: u32, z: u32) -> usize {
+        (z * self.nodes_dims.x * self.nodes_dims.y + y * self.nodes_dims.x + x) as usize
+    }
+
+    /// Get the index of the site given the xyz index of its cell and its basis index
+    fn get_site_idx(&self, UVec3 { x, y, z }: UVec3, basis: usize) -> usize {
+        debug_assert!(x < self.nodes_dims.x);
+        debug_assert!(y < self.nodes_dims.y);
+        debug_assert!(z < self.nodes_dims.z);
+        self.get_data_idx(x, y, z) * self.unit_cell.basis.len() + basis
+    }
+
+    /// Get the index of the node of a site given the xyz index of its cell and its basis index.
+    /// Returns none if the site lies outside the lattice.
+    pub fn get_site(&self, cell: UVec3, basis: usize) -> Option<usize> {
+        self.sites[self.get_site_idx(cell, basis)]
+    }
+
+    /// Record which node sits at a site
+    fn add(&mut self, cell: UVec3, basis: usize, node: usize) {
+        let site_idx = self.get_site_idx(cell, basis);
+        self.sites[site_idx] = Some(node);
+    }
+
+    /// Position of the far corner of the lattice before anything moves
+    pub fn far_corner(&self) -> Vec3 {
+        self.unit_cell.position(self.dims.as_ivec3(), 0)
+    }
+
+    /// Volume of all the unit cells before anything moves, m^3
+    pub fn volume(&self) -> f32 {
+        let [a, b, c] = self.unit_cell.vectors;
+        self.dims.element_product() as f32 * a.dot(b.cross(c)).abs()
+    }
+}
+
+/// Check if the link end position is out of bounds of the lattice
+fn link_out_of_bounds(vec: IVec3, bounds: IVec3) -> bool {
+    vec.cmplt(IVec3::ZERO).any() || vec.cmpge(bounds).any()
+}
+
+/// Get number of nodes in a lattice
+pub fn calc_num_nodes(lattice_dims: UVec3, kind: LatticeKind) -> u32 {
+    debug_assert!(lattice_dims.min_element() > 0);
+    match kind {
+        LatticeKind::SimpleCubic => {
+            let UVec3 { x, y, z } = lattice_dims + UVec3::ONE;
+            match x.checked_mul(y).and_then(|val| val.checked_mul(z)) {
+                None => panic!("overflow while calculating number of lattice nodes"),
+                Some(val) => val,
+            }
+        }
+        // Partially filled cells on the faces make a closed form messy so count the sites instead
+        _ => {
+            // the count doesn't depend on the size of the cell
+            let unit_cell = kind.unit_cell(1.0);
+            let mut count = 0;
+            for cell in cells(lattice_dims) {
+                for basis in 0..unit_cell.basis.len() {
+                    if unit_cell.site_in_bounds(cell, basis, lattice_dims) {
+                        count += 1;
+                    }
+                }
+            }
+            count
+        }
+    }
+}
+
+/// Get the far corner of the box around every node of a lattice before anything moves.
+/// The near corner is the origin, every lattice has a node there and none below it.
+pub fn calc_extent(lattice_dims: UVec3, kind: LatticeKind, cell_len: f32) -> Vec3 {
+    let unit_cell = kind.unit_cell(cell_len);
+    let mut extent = Vec3::ZERO;
+    for cell in cells(lattice_dims) {
+        for basis in 0..unit_cell.basis.len() {
+            if unit_cell.site_in_bounds(cell, basis, lattice_dims) {
+                extent = extent.max(unit_cell.position(cell, basis));
+            }
+        }
+    }
+    extent
+}
+
+/// Get number of links in a lattice given dims and the number of neighbour shells bonded.
+/// Dims are the number of "1x1x1 cubes" along each axis of the lattice.
+pub fn calc_num_links(lattice_dims: UVec3, kind: LatticeKind, num_shells: usize) -> u32 {
+    debug_assert!(lattice_dims.min_element() > 0);
+    debug_assert!((1..=MAX_BOND_SHELLS).contains(&num_shells));
+    match kind {
+        LatticeKind::SimpleCubic => {
+            // links along each axis, two diagonals across each face and four through each cube
+            let [x, y, z] = lattice_dims.to_array().map(u64::from);
+            let (nx, ny, nz) = (x + 1, y + 1, z + 1);
+            let axis = x * ny * nz + nx * y * nz + nx * ny * z;
+            let face_diagonal = 2 * (x * y * nz + nx * y * z + x * ny * z);
+            let body_diagonal = 4 * x * y * z;
+            let total = [axis, face_diagonal, body_diagonal][..num_shells]
+                .iter()
+                .sum::<u64>();
+            match u32::try_from(total) {
+                Err(_) => panic!("overflow while calculating number of lattice links"),
+                Ok(val) => val,
+            }
+        }
+        // Count every bond whose two ends are both inside the lattice
+        _ => {
+            // the count doesn't depend on the size of the cell
+            let unit_cell = kind.unit_cell(1.0);
+            let bond_offsets = unit_cell.bond_offsets(num_shells);
+            let mut count = 0;
+            for cell in cells(lattice_dims) {
+                for (basis, offsets) in bond_offsets.iter().enumerate() {
+                    if !unit_cell.site_in_bounds(cell, basis, lattice_dims) {
+                        continue;
+                    }
+                    for offset in offsets {
+                        let to_cell = cell + offset.cell_offset;
+                        if !link_out_of_bounds(to_cell, lattice_dims.as_ivec3() + IVec3::ONE)
+                            && unit_cell.site_in_bounds(to_cell, offset.basis, lattice_dims)
+                        {
+                            count += 1;
+                        }
+                    }
+                }
+            }
+            count
+        }
+    }
+}
+
+/// Iterate over the xyz index of every cell in a lattice given dims
+fn cells(lattice_dims: UVec3) -> impl Iterator<Item = IVec3> {
+    let nodes_dims = lattice_dims.as_ivec3() + IVec3::ONE;
+    (0..nodes_dims.z).flat_map(move |z| {
+        (0..nodes_dims.y).flat_map(move |y| (0..nodes_dims.x).map(move |x| IVec3::new(x, y, z)))
+    })
+}
+
+/// A small lattice of stiff harmonic links with its corners fixed, for the tests
+#[cfg(test)]
+pub(crate) fn test_params(dims: UVec3, kind: LatticeKind) -> LatticeParams {
+    use crate::boundary::{BoundaryCondition, NodeSelector};
+
+    LatticeParams {
+        dims,
+        kind,
+        cell_len: 1.0,
+        node_mass: 1.0,
+        bond_shells: vec![50.0, 25.0],
+        bending_stiffness: 0.0,
+        link_potential: LinkPotential::Harmonic,
+        damping_coeff: 0.25,
+        breaking_strain: None,
+        boundary_conditions: BoundaryConditions(vec![BoundaryCondition {
+            nodes: NodeSelector::Corners,
+            anchor: Anchor::Fixed,
+        }]),
+        velocity_init: VelocityInit::Uniform,
+        start_vel_min: -0.5,
+        start_vel_max: 0.5,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    const KINDS: [LatticeKind; 6] = [
+        LatticeKind::SimpleCubic,
+        LatticeKind::BodyCenteredCubic,
+        LatticeKind::FaceCenteredCubic,
+        LatticeKind::HexagonalClosePacked,
+        LatticeKind::Diamond,
+        LatticeKind::RockSalt,
+    ];
+
+    #[test]
+    fn generated_counts_match_calc() {
+        let dims = UVec3::new(3, 2, 4);
+        for kind in KINDS {
+            for num_shells in 1..=MAX_BOND_SHELLS {
+                let mut params = test_params(dims, kind);
+                params.bond_shells = vec![1.0; num_shells];
+                let lattice = Lattice::generate(&params, &mut StdRng::seed_from_u64(1));
+
+                assert_eq!(
+                    lattice.nodes.len(),
+                    calc_num_nodes(dims, kind) as usize,
+                    "{kind:?} nodes"
+                );
+                assert_eq!(
+                    lattice.links.len(),
+                    calc_num_links(dims, kind, num_shells) as usize,
+                    "{kind:?} links with {num_shells} shells"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn extent_bounds_the_nodes() {
+        let dims = UVec3::new(3, 2, 4);
+        for kind in KINDS {
+            let lattice =
+                Lattice::generate(&test_params(dims, kind), &mut StdRng::seed_from_u64(1));
+            let pos = &lattice.nodes.pos;
+            let min = pos.iter().fold(Vec3::MAX, |min, pos| min.min(*pos));
+            let max = pos.iter().fold(Vec3::MIN, |max, pos| max.max(*pos));
+
+            assert!(min.abs().max_element() < 1e-5, "{kind:?} starts at {min}");
+            assert!(
+                (max - calc_extent(dims, kind, 1.0)).abs().max_element() < 1e-5,
+                "{kind:?} ends at {max}"
+            );
+        }
+    }
+
+    #[test]
+    fn volume_of_every_kind() {
+        let dims = UVec3::new(3, 2, 4);
+        for kind in KINDS {
+            let lattice = Lattice::new(dims, kind, 2.0);
+            // Hexagonal cells are sqrt(3)/2 * sqrt(8/3) = sqrt(2) times the cube of the same edge
+            let cell_volume = match kind {
+                LatticeKind::HexagonalClosePacked => 8.0 * f32::sqrt(2.0),
+                _ => 8.0,
+            };
+            let volume = lattice.volume();
+            assert!(
+                (volume - 24.0 * cell_volume).abs() < 1e-3,
+                "{kind:?} volume {volume}"
+            );
+        }
+    }
+
+    #[test]
+    fn predicate_selects_nodes() {
+        use crate::boundary::{BoundaryCondition, NodeSelector};
+
+        let mut params = test_params(UVec3::splat(3), LatticeKind::BodyCenteredCubic);
+        params.boundary_conditions = BoundaryConditions(vec![BoundaryCondition {
+            nodes: NodeSelector::Predicate(|cell, basis| cell.y == 0 && basis == 0),
+            anchor: Anchor::Fixed,
+        }]);
+        let lattice = Lattice::generate(&params, &mut StdRng::seed_from_u64(1));
+
+        let nodes = &lattice.nodes;
+        assert!((0..nodes.len()).any(|idx| nodes.is_static(idx)));
+        for idx in 0..nodes.len() {
+            assert_eq!(
+                nodes.is_static(idx),
+                nodes.pos[idx].y.abs() < 1e-4,
+                "node {idx}"
+            );
+        }
+    }
+}
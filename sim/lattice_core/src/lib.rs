@@ -0,0 +1,32 @@
+//! Physics of the crystal lattice without any Bevy dependency.
+//! Builds the lattice, evaluates the link forces and integrates the nodes on plain arrays
+//! so the simulation can be used without an app, a window or an asset server.
+
+mod angle;
+mod boundary;
+mod coloring;
+mod crystal;
+mod damping;
+mod ground;
+mod integrator;
+mod lattice;
+mod node;
+mod potential;
+mod simulation;
+mod thermostat;
+mod velocity;
+
+pub use angle::AngleLink;
+pub use boundary::{Anchor, BoundaryCondition, BoundaryConditions, Constraint, Face, NodeSelector};
+pub use crystal::{BondOffset, LatticeKind, UnitCell};
+pub use damping::DampingModel;
+pub use ground::{ContactModel, Ground};
+pub use integrator::Integrator;
+pub use lattice::{
+    calc_extent, calc_num_links, calc_num_nodes, Lattice, LatticeParams, MAX_BOND_SHELLS,
+};
+pub use node::{Link, Links, Nodes};
+pub use potential::LinkPotential;
+pub use simulation::{BrokenLink, Energy, Simulation, SimulationState};
+pub use thermostat::{TemperatureSchedule, Thermostat, ThermostatModel};
+pub use velocity::VelocityInit;
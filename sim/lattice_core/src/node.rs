@@ -0,0 +1,237 @@
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+
+use crate::boundary::Anchor;
+use crate::potential::LinkPotential;
+
+//-------------------------------------------------------
+// STRUCTS
+//-------------------------------------------------------
+
+/// Nodes!
+/// Every quantity is kept in its own contiguous buffer indexed by the node index.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Nodes {
+    pub pos: Vec<Vec3>,        // meters
+    pub vel: Vec<Vec3>,        // meters/sec
+    pub sum_forces: Vec<Vec3>, //newtons
+    pub mass: Vec<f32>,        // kg
+    /// How the boundary conditions hold each node, none if it is free
+    pub anchor: Vec<Option<Anchor>>,
+}
+
+/// Constants of a link.
+/// Links are massless.
+/// Using link / spring interchangably throughout the code
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Link {
+    pub spring_const: f32,
+    /// Force law of the link, uses the spring constant and original length
+    pub potential: LinkPotential,
+    /// Dashpot coefficient along the bond, N per m/s
+    pub damping_coeff: f32,
+    /// Strain past which the link breaks, none for a link that never breaks
+    pub breaking_strain: Option<f32>,
+    pub orig_length: f32,
+}
+
+/// Every link stored in compressed sparse row form.
+/// The links coming from node i go to the nodes `to[offsets[i]..offsets[i + 1]]`
+/// and their constants sit at the same positions in `params`.
+#[derive(Clone, Debug)]
+pub struct Links {
+    offsets: Vec<u32>,
+    to: Vec<u32>,
+    params: Vec<Link>,
+}
+
+//-------------------------------------------------------
+// IMPLEMENTATIONS
+//-------------------------------------------------------
+
+impl Nodes {
+    /// Create empty buffers with room for the given number of nodes
+    pub fn with_capacity(capacity: usize) -> Self {
+        Nodes {
+            pos: Vec::with_capacity(capacity),
+            vel: Vec::with_capacity(capacity),
+            sum_forces: Vec::with_capacity(capacity),
+            mass: Vec::with_capacity(capacity),
+            anchor: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Add a node and get its index
+    pub fn push(&mut self, pos: Vec3, vel: Vec3, mass: f32, anchor: Option<Anchor>) -> usize {
+        self.pos.push(pos);
+        self.vel.push(vel);
+        self.sum_forces.push(Vec3::ZERO);
+        self.mass.push(mass);
+        self.anchor.push(anchor);
+        self.pos.len() - 1
+    }
+
+    pub fn len(&self) -> usize {
+        self.pos.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos.is_empty()
+    }
+
+    /// Check if the node is held fixed and should not move
+    pub fn is_static(&self, idx: usize) -> bool {
+        matches!(self.anchor[idx], Some(Anchor::Fixed))
+    }
+
+    /// Number of directions the node can move in
+    pub fn degrees_of_freedom(&self, idx: usize) -> u32 {
+        match self.anchor[idx] {
+            None => 3,
+            Some(Anchor::Fixed) => 0,
+            Some(Anchor::Partial(constraint)) => constraint.degrees_of_freedom(),
+        }
+    }
+
+    /// Number of directions all of the nodes together can move in, static nodes have none
+    pub fn total_degrees_of_freedom(&self) -> u32 {
+        (0..self.len())
+            .map(|idx| self.degrees_of_freedom(idx))
+            .sum()
+    }
+
+    /// Kinetic energy of all non static nodes, J
+    pub fn kinetic_energy(&self) -> f32 {
+        (0..self.len())
+            .filter(|idx| !self.is_static(*idx))
+            .map(|idx| 0.5 * self.mass[idx] * self.vel[idx].length_squared())
+            .sum()
+    }
+
+    /// Kinetic temperature, 2 KE / DOF, in energy units, J. Zero when nothing can move.
+    pub fn temperature(&self) -> f32 {
+        match self.total_degrees_of_freedom() {
+            0 => 0.0,
+            dof => 2.0 * self.kinetic_energy() / dof as f32,
+        }
+    }
+}
+
+impl Link {
+    /// Create a new link.
+    pub fn new(
+        spring_const: f32,
+        potential: LinkPotential,
+        damping_coeff: f32,
+        breaking_strain: Option<f32>,
+        orig_length: f32,
+    ) -> Self {
+        Link {
+            spring_const,
+            potential,
+            damping_coeff,
+            breaking_strain,
+            orig_length,
+        }
+    }
+
+    /// Strain of the link when stretched to the given length, positive in tension
+    pub fn strain(&self, length: f32) -> f32 {
+        (length - self.orig_length) / self.orig_length
+    }
+
+    /// Elastic energy stored in the link when stretched to the given length, J
+    pub fn potential_energy(&self, length: f32) -> f32 {
+        self.potential
+            .energy(self.spring_const, self.orig_length, length)
+    }
+
+    /// Force along the link axis when stretched to the given length, negative in tension
+    pub fn force(&self, length: f32) -> f32 {
+        self.potential
+            .force(self.spring_const, self.orig_length, length)
+    }
+}
+
+impl Links {
+    /// Pack links given as (from, to, constants) between the given number of nodes.
+    /// Links keep their relative order within each from node.
+    pub fn new(num_nodes: usize, mut links: Vec<(usize, usize, Link)>) -> Self {
+        links.sort_by_key(|(from, _, _)| *from);
+
+        let mut offsets = vec![0; num_nodes + 1];
+        for (from, _, _) in links.iter() {
+            offsets[from + 1] += 1;
+        }
+        for idx in 0..num_nodes {
+            offsets[idx + 1] += offsets[idx];
+        }
+
+        let (to, params) = links
+            .into_iter()
+            .map(|(_, to, link)| (to as u32, link))
+            .unzip();
+        Links {
+            offsets,
+            to,
+            params,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.to.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.to.is_empty()
+    }
+
+    /// Range of positions in the link buffers holding the links from a node
+    pub fn row(&self, from: usize) -> std::ops::Range<usize> {
+        self.offsets[from] as usize..self.offsets[from + 1] as usize
+    }
+
+    /// Index of the node the link at the given position goes to
+    pub fn to(&self, link_idx: usize) -> usize {
+        self.to[link_idx] as usize
+    }
+
+    /// Constants of the link at the given position
+    pub fn params(&self, link_idx: usize) -> &Link {
+        &self.params[link_idx]
+    }
+
+    /// Check if there is a link between two nodes in either direction
+    pub fn joins(&self, a: usize, b: usize) -> bool {
+        let has = |from: usize, to: usize| self.row(from).any(|link_idx| self.to(link_idx) == to);
+        has(a, b) || has(b, a)
+    }
+
+    /// Iterate over every link as (from, to, constants)
+    pub fn iter(&self) -> impl Iterator<Item = (usize, usize, &Link)> {
+        (0..self.offsets.len() - 1).flat_map(move |from| {
+            self.row(from)
+                .map(move |link_idx| (from, self.to(link_idx), &self.params[link_idx]))
+        })
+    }
+
+    /// Keep only the links the function returns true for given (from, to, constants)
+    pub fn retain(&mut self, mut keep: impl FnMut(usize, usize, &Link) -> bool) {
+        let mut write = 0;
+        let mut row_start = 0;
+        for from in 0..self.offsets.len() - 1 {
+            let row_end = self.offsets[from + 1] as usize;
+            for read in row_start..row_end {
+                if keep(from, self.to[read] as usize, &self.params[read]) {
+                    self.to.swap(write, read);
+                    self.params.swap(write, read);
+                    write += 1;
+                }
+            }
+            row_start = row_end;
+            self.offsets[from + 1] = write as u32;
+        }
+        self.to.truncate(write);
+        self.params.truncate(write);
+    }
+}
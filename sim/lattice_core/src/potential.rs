@@ -0,0 +1,125 @@
+use serde::{Deserialize, Serialize};
+
+//-------------------------------------------------------
+// STRUCTS
+//-------------------------------------------------------
+
+/// Force law of a link as a function of its length.
+/// Every potential has its minimum at the link's original length so the lattice starts at rest.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkPotential {
+    /// U = 1/2 k x^2, the linear spring
+    #[default]
+    Harmonic,
+    /// U = D (1 - exp(-a x))^2
+    Morse { well_depth: f32, width: f32 },
+    /// 12-6 Lennard-Jones with sigma chosen so the minimum sits at the original length,
+    /// shifted so U is zero there. Always bonded, there is no cutoff.
+    LennardJones { well_depth: f32 },
+    /// U = -1/2 k R^2 ln(1 - (x/R)^2), finitely extensible spring that can't stretch past R
+    Fene { max_extension: f32 },
+    /// U = 1/2 k x^2 + 1/3 c x^3, a negative c softens the link in tension
+    CubicAnharmonic { cubic_coeff: f32 },
+}
+
+//-------------------------------------------------------
+// IMPLEMENTATIONS
+//-------------------------------------------------------
+
+impl LinkPotential {
+    /// How close the FENE link may get to its max extension before the force stops growing.
+    /// Past it the energy grows linearly so the force stays -dU/dr.
+    const FENE_MAX_RATIO: f32 = 0.99;
+
+    /// Energy stored in a link of the given length, J.
+    /// x in the docs above is length - orig_length.
+    pub fn energy(&self, spring_const: f32, orig_length: f32, length: f32) -> f32 {
+        let x = length - orig_length;
+        match *self {
+            LinkPotential::Harmonic => 0.5 * spring_const * x * x,
+            LinkPotential::Morse { well_depth, width } => {
+                let decay = 1.0 - f32::exp(-width * x);
+                well_depth * decay * decay
+            }
+            LinkPotential::LennardJones { well_depth } => {
+                // (sigma / r)^6 where sigma = orig_length / 2^(1/6)
+                let inv_r6 = 0.5 * (orig_length / length).powi(6);
+                4.0 * well_depth * (inv_r6 * inv_r6 - inv_r6) + well_depth
+            }
+            LinkPotential::Fene { max_extension } => {
+                let ratio = (x / max_extension).clamp(-Self::FENE_MAX_RATIO, Self::FENE_MAX_RATIO);
+                let clamped = -0.5
+                    * spring_const
+                    * max_extension
+                    * max_extension
+                    * f32::ln(1.0 - ratio * ratio);
+                // Work done against the saturated force past the clamp
+                let past = x.abs() - ratio.abs() * max_extension;
+                let saturated = spring_const * ratio.abs() * max_extension / (1.0 - ratio * ratio);
+                clamped + saturated * past
+            }
+            LinkPotential::CubicAnharmonic { cubic_coeff } => {
+                0.5 * spring_const * x * x + cubic_coeff * x * x * x / 3.0
+            }
+        }
+    }
+
+    /// Force the link exerts along its axis at the given length, -dU/dr.
+    /// Negative when the link is pulling its two nodes together.
+    pub fn force(&self, spring_const: f32, orig_length: f32, length: f32) -> f32 {
+        let x = length - orig_length;
+        match *self {
+            LinkPotential::Harmonic => -spring_const * x,
+            LinkPotential::Morse { well_depth, width } => {
+                let decay = f32::exp(-width * x);
+                -2.0 * well_depth * width * decay * (1.0 - decay)
+            }
+            LinkPotential::LennardJones { well_depth } => {
+                let inv_r6 = 0.5 * (orig_length / length).powi(6);
+                24.0 * well_depth * (2.0 * inv_r6 * inv_r6 - inv_r6) / length
+            }
+            LinkPotential::Fene { max_extension } => {
+                let ratio = (x / max_extension).clamp(-Self::FENE_MAX_RATIO, Self::FENE_MAX_RATIO);
+                -spring_const * ratio * max_extension / (1.0 - ratio * ratio)
+            }
+            LinkPotential::CubicAnharmonic { cubic_coeff } => {
+                -spring_const * x - cubic_coeff * x * x
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn force_is_the_slope_of_the_energy() {
+        let potentials = [
+            LinkPotential::Harmonic,
+            LinkPotential::Morse {
+                well_depth: 1.0,
+                width: 2.0,
+            },
+            LinkPotential::LennardJones { well_depth: 1.0 },
+            LinkPotential::Fene { max_extension: 0.5 },
+            LinkPotential::CubicAnharmonic { cubic_coeff: -1.0 },
+        ];
+        let step = 1e-3;
+        for potential in potentials {
+            // Past both ends of the FENE clamp as well as inside it
+            for length in [0.4, 0.52, 0.8, 1.0, 1.2, 1.45, 1.6] {
+                let slope = (potential.energy(10.0, 1.0, length + step)
+                    - potential.energy(10.0, 1.0, length - step))
+                    / (2.0 * step);
+                let force = potential.force(10.0, 1.0, length);
+                assert!(
+                    (force + slope).abs() < 1e-2 * force.abs().max(1.0),
+                    "{potential:?} at {length}: force {force}, -dU/dr {}",
+                    -slope
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,832 @@
+use glam::{Mat3, UVec3, Vec3};
+use rand::Rng;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::angle::AngleLink;
+use crate::boundary::Anchor;
+use crate::coloring::{ColoredLink, LinkColoring, SharedSlice};
+use crate::crystal::LatticeKind;
+use crate::damping::DampingModel;
+use crate::ground::{ContactModel, Ground};
+use crate::integrator::{Integrator, StageState};
+use crate::lattice::Lattice;
+use crate::node::{Link, Links, Nodes};
+use crate::thermostat::Thermostat;
+
+//-------------------------------------------------------
+// STRUCTS
+//-------------------------------------------------------
+
+/// A lattice along with how it is advanced in time
+pub struct Simulation {
+    pub lattice: Lattice,
+    pub integrator: Integrator,
+    pub damping: DampingModel,
+    pub thermostat: Thermostat,
+    /// Uniform acceleration on every node, m/s^2. Changed through `set_environment`.
+    pub gravity: Vec3,
+    /// Floor the nodes rest on, none to let them fall forever. Changed through `set_environment`.
+    pub ground: Option<Ground>,
+    /// Energies after the last step
+    pub energy: Energy,
+    /// Simulated time since the start, s
+    pub time: f64,
+    /// Steps taken since the start
+    pub steps: u64,
+    /// Sum of r ⊗ f over every link and angle link from the last force evaluation, J.
+    /// f acts on the to node of a link or an end of an angle link, r goes to it from the
+    /// from node or the vertex.
+    pub virial: Mat3,
+    /// Scratch space for the multi-stage integrators, indexed the same as the nodes
+    stages: Vec<StageState>,
+    /// Colour classes of the links, rebuilt whenever links are removed
+    coloring: LinkColoring,
+    /// Threads the link forces are evaluated on, none to evaluate them on the calling thread
+    pool: Option<rayon::ThreadPool>,
+    /// Damping power of every link from the last force evaluation, indexed the same as the links
+    link_power: Vec<f32>,
+    /// r ⊗ f of every link from the last force evaluation, indexed the same as the links
+    link_virial: Vec<Mat3>,
+}
+
+/// Energy bookkeeping of the lattice
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+pub struct Energy {
+    /// Kinetic energy of all non static nodes, J
+    pub kinetic: f32,
+    /// Elastic energy stored in all links, J
+    pub potential: f32,
+    /// Kinetic plus potential energy, J
+    pub total: f32,
+    /// Running total of the work removed by damping since the start, J
+    pub dissipated_work: f32,
+}
+
+/// A link that was stretched past its breaking strain and removed
+#[derive(Clone, Copy, Debug)]
+pub struct BrokenLink {
+    pub from: usize,
+    pub to: usize,
+    /// Strain of the link at the moment it failed
+    pub strain: f32,
+}
+
+/// Everything needed to carry on a simulation exactly where it left off.
+/// The integrator, damping model, thermostat and threads are chosen again when it is restored.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SimulationState {
+    /// Number of unit cells along each axis
+    pub dims: UVec3,
+    pub kind: LatticeKind,
+    /// Edge length of the conventional unit cell, m
+    pub cell_len: f32,
+    /// Every node in lattice order, so a node's index is its lattice index
+    pub nodes: Nodes,
+    /// Every intact link as (from, to, constants), the ends given by lattice index
+    pub links: Vec<(usize, usize, Link)>,
+    #[serde(default)]
+    pub angles: Vec<AngleLink>,
+    pub energy: Energy,
+    pub time: f64,
+    pub steps: u64,
+    /// Friction of every thermostat in a Nosé–Hoover chain, 1/s
+    #[serde(default)]
+    pub thermostat_chain: Vec<f32>,
+}
+
+//-------------------------------------------------------
+// IMPLEMENTATIONS
+//-------------------------------------------------------
+
+impl Simulation {
+    /// Fewest links each thread is handed at once, smaller batches cost more to hand out than to evaluate
+    const MIN_LINKS_PER_TASK: usize = 256;
+
+    /// Create a simulation of the lattice.
+    /// The link forces are evaluated on the given number of threads, 0 uses one per core.
+    /// The results are exactly the same for any number of threads.
+    pub fn new(
+        lattice: Lattice,
+        integrator: Integrator,
+        damping: DampingModel,
+        thermostat: Thermostat,
+        threads: usize,
+    ) -> Self {
+        let pool = match threads {
+            1 => None,
+            _ => Some(
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .build()
+                    .expect("could not start the force evaluation threads"),
+            ),
+        };
+        let coloring = LinkColoring::new(&lattice.links, lattice.nodes.len());
+
+        let mut simulation = Simulation {
+            lattice,
+            integrator,
+            damping,
+            thermostat,
+            gravity: Vec3::ZERO,
+            ground: None,
+            energy: Energy::default(),
+            time: 0.0,
+            steps: 0,
+            virial: Mat3::ZERO,
+            stages: Vec::new(),
+            coloring,
+            pool,
+            link_power: Vec::new(),
+            link_virial: Vec::new(),
+        };
+        simulation.refresh();
+        simulation
+    }
+
+    /// Put the lattice under the given gravity and over the given ground, none for no ground.
+    /// The forces and energies are brought up to date with them, the dissipated work is kept.
+    pub fn set_environment(&mut self, gravity: Vec3, ground: Option<Ground>) {
+        self.gravity = gravity;
+        self.ground = ground;
+        self.refresh();
+    }
+
+    /// Evaluate the forces, virial and energies at the current state
+    fn refresh(&mut self) {
+        self.update_link_physics();
+        self.energy.kinetic = self.kinetic_energy();
+        self.energy.potential = self.potential_energy();
+        self.energy.total = self.energy.kinetic + self.energy.potential;
+    }
+
+    /// Carry on a simulation from a saved state with the given integrator, damping, thermostat and threads
+    pub fn restore(
+        state: SimulationState,
+        integrator: Integrator,
+        damping: DampingModel,
+        mut thermostat: Thermostat,
+        threads: usize,
+    ) -> Self {
+        let links = Links::new(state.nodes.len(), state.links);
+        let lattice = Lattice::from_parts(
+            state.dims,
+            state.kind,
+            state.cell_len,
+            state.nodes,
+            links,
+            state.angles,
+        );
+        thermostat.set_chain(&state.thermostat_chain);
+        let mut simulation = Simulation::new(lattice, integrator, damping, thermostat, threads);
+        simulation.energy = state.energy;
+        simulation.time = state.time;
+        simulation.steps = state.steps;
+        simulation
+    }
+
+    /// Take a copy of everything needed to restore the simulation later
+    pub fn state(&self) -> SimulationState {
+        let lattice = &self.lattice;
+        SimulationState {
+            dims: lattice.dims,
+            kind: lattice.kind,
+            cell_len: lattice.cell_len,
+            nodes: lattice.nodes.clone(),
+            links: lattice
+                .links
+                .iter()
+                .map(|(from, to, link)| (from, to, link.clone()))
+                .collect(),
+            angles: lattice.angles.clone(),
+            energy: self.energy,
+            time: self.time,
+            steps: self.steps,
+            thermostat_chain: self.thermostat.chain().to_vec(),
+        }
+    }
+
+    /// Update the state of the nodes and their positions using the selected integrator.
+    /// The link forces are evaluated as many times as the integrator needs.
+    /// The thermostat draws any random forces it needs for the step from the given rng.
+    pub fn step(&mut self, delta_t: f32, rng: &mut impl Rng) {
+        self.thermostat
+            .begin_step(&self.lattice.nodes, self.time, delta_t, rng);
+
+        // Damping power is integrated over the step with the same stage weights as the integrator
+        let dissipated_work;
+        let num_nodes = self.lattice.nodes.len();
+
+        match self.integrator {
+            Integrator::SemiImplicitEuler => {
+                dissipated_work = self.update_link_physics() * delta_t;
+                let nodes = &mut self.lattice.nodes;
+                for idx in 0..num_nodes {
+                    if !nodes.is_static(idx) {
+                        let acc = nodes.sum_forces[idx] / nodes.mass[idx];
+                        nodes.vel[idx] += acc * delta_t;
+                        nodes.pos[idx] += nodes.vel[idx] * delta_t;
+                    }
+                }
+            }
+            Integrator::VelocityVerlet => {
+                // first half kick and drift with the forces at the current position
+                let start_power = self.update_link_physics();
+                let nodes = &mut self.lattice.nodes;
+                for idx in 0..num_nodes {
+                    if !nodes.is_static(idx) {
+                        let acc = nodes.sum_forces[idx] / nodes.mass[idx];
+                        nodes.vel[idx] += 0.5 * acc * delta_t;
+                        nodes.pos[idx] += nodes.vel[idx] * delta_t;
+                    }
+                }
+                // second half kick with the forces at the new position
+                let end_power = self.update_link_physics();
+                dissipated_work = 0.5 * (start_power + end_power) * delta_t;
+                let nodes = &mut self.lattice.nodes;
+                for idx in 0..num_nodes {
+                    if !nodes.is_static(idx) {
+                        let acc = nodes.sum_forces[idx] / nodes.mass[idx];
+                        nodes.vel[idx] += 0.5 * acc * delta_t;
+                    }
+                }
+            }
+            Integrator::Leapfrog => {
+                // half drift, full kick with the forces at the midpoint, half drift
+                let nodes = &mut self.lattice.nodes;
+                for idx in 0..num_nodes {
+                    if !nodes.is_static(idx) {
+                        nodes.pos[idx] += 0.5 * nodes.vel[idx] * delta_t;
+                    }
+                }
+                dissipated_work = self.update_link_physics() * delta_t;
+                let nodes = &mut self.lattice.nodes;
+                for idx in 0..num_nodes {
+                    if !nodes.is_static(idx) {
+                        let acc = nodes.sum_forces[idx] / nodes.mass[idx];
+                        nodes.vel[idx] += acc * delta_t;
+                        nodes.pos[idx] += 0.5 * nodes.vel[idx] * delta_t;
+                    }
+                }
+            }
+            Integrator::RungeKutta4 => {
+                self.stages.clear();
+                let nodes = &self.lattice.nodes;
+                self.stages
+                    .extend(
+                        nodes
+                            .pos
+                            .iter()
+                            .zip(nodes.vel.iter())
+                            .map(|(pos, vel)| StageState {
+                                pos: *pos,
+                                vel: *vel,
+                                ..Default::default()
+                            }),
+                    );
+
+                let mut sum_power = 0.0;
+                for (stage_idx, weight) in Integrator::RK4_WEIGHTS.iter().enumerate() {
+                    sum_power += *weight * self.update_link_physics();
+                    let nodes = &mut self.lattice.nodes;
+                    for (idx, stage) in self.stages.iter_mut().enumerate() {
+                        if nodes.is_static(idx) {
+                            continue;
+                        }
+                        let acc = nodes.sum_forces[idx] / nodes.mass[idx];
+
+                        stage.sum_dpos += *weight * nodes.vel[idx];
+                        stage.sum_dvel += *weight * acc;
+
+                        // Move the node to where the next stage is evaluated, or to the final result
+                        match Integrator::RK4_STAGE_OFFSETS.get(stage_idx) {
+                            Some(offset) => {
+                                nodes.pos[idx] = stage.pos + nodes.vel[idx] * *offset * delta_t;
+                                nodes.vel[idx] = stage.vel + acc * *offset * delta_t;
+                            }
+                            None => {
+                                nodes.pos[idx] = stage.pos + stage.sum_dpos * delta_t / 6.0;
+                                nodes.vel[idx] = stage.vel + stage.sum_dvel * delta_t / 6.0;
+                            }
+                        }
+                    }
+                }
+                dissipated_work = sum_power * delta_t / 6.0;
+            }
+        }
+
+        let impact_work = self.resolve_ground_impulses();
+
+        self.energy.kinetic = self.kinetic_energy();
+        self.energy.potential = self.potential_energy();
+        self.energy.total = self.energy.kinetic + self.energy.potential;
+        self.energy.dissipated_work += dissipated_work + impact_work;
+        self.time += delta_t as f64;
+        self.steps += 1;
+    }
+
+    /// Sum up the spring, bending, damping, thermostat, gravity and ground contact forces on each node at the nodes' current positions and velocities.
+    /// Overwrites the node forces and the virial and does not modify any other state so it can be called
+    /// more than once per step.
+    /// Returns the power being removed by damping at this state, W.
+    pub fn update_link_physics(&mut self) -> f32 {
+        let nodes = &mut self.lattice.nodes;
+        let links = &self.lattice.links;
+        let damping = self.damping;
+        let thermostat = &self.thermostat;
+        let (gravity, ground) = (self.gravity, self.ground);
+
+        nodes.sum_forces.fill(Vec3::ZERO);
+        self.link_power.resize(links.len(), 0.0);
+        self.link_virial.resize(links.len(), Mat3::ZERO);
+
+        let (pos, vel) = (&nodes.pos, &nodes.vel);
+        let forces = SharedSlice::new(&mut nodes.sum_forces);
+        let link_power = SharedSlice::new(&mut self.link_power);
+        let link_virial = SharedSlice::new(&mut self.link_virial);
+
+        let apply_link = |colored: &ColoredLink| {
+            let from = colored.from as usize;
+            let link_idx = colored.link_idx as usize;
+            let to = links.to(link_idx);
+            let link = links.params(link_idx);
+
+            let delta_pos = pos[to] - pos[from];
+            let force_dir = delta_pos.normalize();
+            let length = delta_pos.length();
+
+            // Dashpot acts on how fast the two ends are moving apart along the bond axis
+            let relative_speed = (vel[to] - vel[from]).dot(force_dir);
+            let damping_coeff = damping.link_coeff(link.spring_const, link.damping_coeff);
+            let damping_force = -damping_coeff * relative_speed;
+
+            // Tension in the link, pulls both nodes together when stretched
+            let total_force = link.force(length) + damping_force;
+            let to_force = total_force * force_dir;
+
+            // this force is applied in the axis colinear from node 1 to node 2.
+            // SAFETY: the classes are evaluated one after another and no two links of a class share a
+            // node, so no other thread writes these nodes. Each link is in one class only, so its
+            // slots are written once.
+            unsafe {
+                *link_power.get_mut(link_idx) = damping_coeff * relative_speed * relative_speed;
+                *link_virial.get_mut(link_idx) = outer(delta_pos, to_force);
+                *forces.get_mut(from) -= to_force;
+                *forces.get_mut(to) += to_force;
+            }
+        };
+
+        // Each class has to finish before the next starts since they share nodes
+        match &self.pool {
+            Some(pool) => pool.install(|| {
+                for class in self.coloring.classes() {
+                    class
+                        .par_iter()
+                        .with_min_len(Self::MIN_LINKS_PER_TASK)
+                        .for_each(apply_link);
+                }
+            }),
+            None => {
+                for class in self.coloring.classes() {
+                    class.iter().for_each(apply_link);
+                }
+            }
+        }
+
+        // Summed in link order so the total doesn't depend on the number of threads
+        let mut dissipated_power: f32 = self.link_power.iter().sum();
+        self.virial = self.link_virial.iter().sum();
+
+        // Angle links are few next to the links so they are summed on this thread
+        for angle in self.lattice.angles.iter() {
+            let end_forces = angle.forces(&nodes.pos);
+            for (end, force) in angle.ends.iter().zip(end_forces) {
+                nodes.sum_forces[*end] += force;
+                nodes.sum_forces[angle.vertex] -= force;
+                self.virial += outer(nodes.pos[*end] - nodes.pos[angle.vertex], force);
+            }
+        }
+
+        for idx in 0..nodes.len() {
+            if nodes.is_static(idx) {
+                continue;
+            }
+
+            // Drag on the nodes themselves for the models that have it
+            let drag_coeff = damping.node_coeff(nodes.mass[idx]);
+            dissipated_power += drag_coeff * nodes.vel[idx].length_squared();
+            nodes.sum_forces[idx] -= drag_coeff * nodes.vel[idx];
+
+            // Thermostat exchanges heat with the node, not counted as dissipated work
+            nodes.sum_forces[idx] += thermostat.force(idx, nodes.mass[idx], nodes.vel[idx]);
+
+            nodes.sum_forces[idx] += nodes.mass[idx] * gravity;
+            if let Some(contact) = ground.and_then(|ground| {
+                ground.penalty_force(nodes.pos[idx], nodes.vel[idx], nodes.mass[idx])
+            }) {
+                nodes.sum_forces[idx] += contact.force;
+                dissipated_power += contact.dissipated_power;
+            }
+
+            // Partially constrained nodes only feel the part of the force they are free to move along.
+            // Their velocity starts in the allowed directions so it stays there.
+            if let Some(Anchor::Partial(constraint)) = nodes.anchor[idx] {
+                nodes.sum_forces[idx] = constraint.project(nodes.sum_forces[idx]);
+            }
+        }
+
+        dissipated_power
+    }
+
+    /// Put every node that went below an impulse contact ground back on it and bounce it.
+    /// Returns the energy the impacts removed, the kinetic energy they took less the potential
+    /// energy the nodes gained by being put back, J.
+    fn resolve_ground_impulses(&mut self) -> f32 {
+        let Some(ground) = self.ground else {
+            return 0.0;
+        };
+        let nodes = &self.lattice.nodes;
+        let any_below =
+            (0..nodes.len()).any(|idx| !nodes.is_static(idx) && nodes.pos[idx].y < ground.height);
+        if ground.contact != ContactModel::Impulse || !any_below {
+            return 0.0;
+        }
+
+        // Putting nodes back on the ground raises their gravitational energy and moves their links,
+        // that energy is injected by the contact so it comes off the work it removes
+        let start_potential = self.potential_energy();
+        let nodes = &mut self.lattice.nodes;
+        let mut impact_work = 0.0;
+        for idx in 0..nodes.len() {
+            if nodes.is_static(idx) {
+                continue;
+            }
+            let (start_pos, mut pos, mut vel) = (nodes.pos[idx], nodes.pos[idx], nodes.vel[idx]);
+            impact_work += ground.resolve_impulse(&mut pos, &mut vel, nodes.mass[idx]);
+            // Partially constrained nodes only take the part of the correction they can move along
+            if let Some(Anchor::Partial(constraint)) = nodes.anchor[idx] {
+                pos = start_pos + constraint.project(pos - start_pos);
+                vel = constraint.project(vel);
+            }
+            nodes.pos[idx] = pos;
+            nodes.vel[idx] = vel;
+        }
+        impact_work + start_potential - self.potential_energy()
+    }
+
+    /// Remove every link whose strain is past its breaking strain.
+    /// Only the link is removed, the nodes it joined stay in the lattice.
+    pub fn break_links(&mut self) -> Vec<BrokenLink> {
+        let pos = &self.lattice.nodes.pos;
+        let mut broken = Vec::new();
+        self.lattice.links.retain(|from, to, link| {
+            let strain = link.strain(pos[from].distance(pos[to]));
+            if link
+                .breaking_strain
+                .is_some_and(|breaking| strain > breaking)
+            {
+                broken.push(BrokenLink { from, to, strain });
+                return false;
+            }
+            true
+        });
+
+        // The links moved so the classes have to be rebuilt, and the bends of the lost bonds go with them
+        if !broken.is_empty() {
+            let links = &self.lattice.links;
+            self.lattice
+                .angles
+                .retain(|angle| angle.ends.iter().all(|end| links.joins(angle.vertex, *end)));
+            self.coloring = LinkColoring::new(&self.lattice.links, self.lattice.nodes.len());
+        }
+        broken
+    }
+
+    /// Kinetic energy of all non static nodes, J
+    pub fn kinetic_energy(&self) -> f32 {
+        self.lattice.nodes.kinetic_energy()
+    }
+
+    /// Elastic energy stored in all links, angle links and ground contacts plus the gravitational
+    /// energy of the non static nodes, measured from the origin, J
+    pub fn potential_energy(&self) -> f32 {
+        let pos = &self.lattice.nodes.pos;
+        let stretch: f32 = self
+            .lattice
+            .links
+            .iter()
+            .map(|(from, to, link)| link.potential_energy(pos[from].distance(pos[to])))
+            .sum();
+        let bend: f32 = self
+            .lattice
+            .angles
+            .iter()
+            .map(|angle| angle.potential_energy(pos))
+            .sum();
+        let nodes = &self.lattice.nodes;
+        let external: f32 = match (self.gravity != Vec3::ZERO, self.ground) {
+            (false, None) => 0.0,
+            (_, ground) => (0..nodes.len())
+                .filter(|idx| !nodes.is_static(*idx))
+                .map(|idx| {
+                    let contact = ground.map_or(0.0, |ground| ground.potential_energy(pos[idx]));
+                    contact - nodes.mass[idx] * self.gravity.dot(pos[idx])
+                })
+                .sum(),
+        };
+        stretch + bend + external
+    }
+
+    /// Number of directions all of the nodes together can move in, static nodes have none
+    pub fn degrees_of_freedom(&self) -> u32 {
+        self.lattice.nodes.total_degrees_of_freedom()
+    }
+
+    /// Kinetic temperature, 2 KE / DOF. Given in energy units, J, as if Boltzmann's constant was 1
+    pub fn temperature(&self) -> f32 {
+        self.lattice.nodes.temperature()
+    }
+
+    /// Virial stress of the lattice, Pa. Kinetic part from every non static node plus the
+    /// link virial, over the volume of the unit cells. Positive is tension.
+    pub fn stress(&self) -> Mat3 {
+        let nodes = &self.lattice.nodes;
+        let kinetic: Mat3 = (0..nodes.len())
+            .filter(|idx| !nodes.is_static(*idx))
+            .map(|idx| nodes.mass[idx] * outer(nodes.vel[idx], nodes.vel[idx]))
+            .sum();
+        -(kinetic + self.virial) / self.lattice.volume()
+    }
+
+    /// Pressure, minus the mean of the normal stresses, Pa. Negative when the lattice is in tension.
+    pub fn pressure(&self) -> f32 {
+        let stress = self.stress();
+        -(stress.x_axis.x + stress.y_axis.y + stress.z_axis.z) / 3.0
+    }
+
+    /// Mass weighted average position of all nodes, m
+    pub fn center_of_mass(&self) -> Vec3 {
+        let nodes = &self.lattice.nodes;
+        let total_mass: f32 = nodes.mass.iter().sum();
+        let weighted: Vec3 = (0..nodes.len())
+            .map(|idx| nodes.mass[idx] * nodes.pos[idx])
+            .sum();
+        weighted / total_mass
+    }
+
+    /// Total linear momentum of all nodes, kg m/s
+    pub fn momentum(&self) -> Vec3 {
+        let nodes = &self.lattice.nodes;
+        (0..nodes.len())
+            .map(|idx| nodes.mass[idx] * nodes.vel[idx])
+            .sum()
+    }
+
+    /// Largest strain of any intact link, none if every link is broken
+    pub fn max_strain(&self) -> Option<f32> {
+        let pos = &self.lattice.nodes.pos;
+        self.lattice
+            .links
+            .iter()
+            .map(|(from, to, link)| link.strain(pos[from].distance(pos[to])))
+            .reduce(f32::max)
+    }
+}
+
+/// Outer product a b^T
+fn outer(a: Vec3, b: Vec3) -> Mat3 {
+    Mat3::from_cols(a * b.x, a * b.y, a * b.z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boundary::{BoundaryCondition, BoundaryConditions, Constraint, Face, NodeSelector};
+    use crate::crystal::LatticeKind;
+    use crate::lattice::test_params;
+    use crate::thermostat::{TemperatureSchedule, ThermostatModel};
+    use glam::UVec3;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    const TIME_STEP: f32 = 0.005;
+    const STEPS: usize = 4000;
+
+    /// Simulation of the lattice without damping or a thermostat
+    fn simulation_of(lattice: Lattice) -> Simulation {
+        let thermostat = Thermostat::new(ThermostatModel::None, TemperatureSchedule(vec![]));
+        Simulation::new(
+            lattice,
+            Integrator::VelocityVerlet,
+            DampingModel::None,
+            thermostat,
+            1,
+        )
+    }
+
+    fn simulation(kind: LatticeKind, integrator: Integrator, damping: DampingModel) -> Simulation {
+        let lattice = Lattice::generate(
+            &test_params(UVec3::splat(3), kind),
+            &mut StdRng::seed_from_u64(11),
+        );
+        let thermostat = Thermostat::new(ThermostatModel::None, TemperatureSchedule(vec![]));
+        Simulation::new(lattice, integrator, damping, thermostat, 1)
+    }
+
+    /// Largest change of the total energy plus the work done by damping over the run, relative
+    /// to the starting energy
+    fn max_energy_error(mut sim: Simulation) -> f32 {
+        let mut rng = StdRng::seed_from_u64(0);
+        let start = sim.energy.total;
+        let mut max_error: f32 = 0.0;
+        for _ in 0..STEPS {
+            sim.step(TIME_STEP, &mut rng);
+            let error = sim.energy.total + sim.energy.dissipated_work - start;
+            max_error = max_error.max(error.abs());
+        }
+        max_error / start
+    }
+
+    #[test]
+    fn energy_is_conserved_without_damping() {
+        // Semi-implicit Euler is only first order so its energy swings the most
+        for (integrator, tolerance) in [
+            (Integrator::SemiImplicitEuler, 0.05),
+            (Integrator::VelocityVerlet, 0.005),
+            (Integrator::Leapfrog, 0.005),
+            (Integrator::RungeKutta4, 0.001),
+        ] {
+            for kind in [LatticeKind::SimpleCubic, LatticeKind::FaceCenteredCubic] {
+                let sim = simulation(kind, integrator, DampingModel::None);
+                let error = max_energy_error(sim);
+                assert!(
+                    error < tolerance,
+                    "{integrator:?} on {kind:?} drifted by {error}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn energy_is_conserved_with_bending() {
+        for kind in [LatticeKind::SimpleCubic, LatticeKind::FaceCenteredCubic] {
+            let mut params = test_params(UVec3::splat(3), kind);
+            params.bending_stiffness = 5.0;
+            let lattice = Lattice::generate(&params, &mut StdRng::seed_from_u64(11));
+            assert!(!lattice.angles.is_empty());
+            let error = max_energy_error(simulation_of(lattice));
+            assert!(error < 0.005, "bending {kind:?} drifted by {error}");
+        }
+    }
+
+    #[test]
+    fn damping_work_accounts_for_the_lost_energy() {
+        for damping in [
+            DampingModel::BondDashpot,
+            DampingModel::Rayleigh {
+                mass_coeff: 0.1,
+                stiffness_coeff: 0.005,
+            },
+            DampingModel::ViscousDrag { coeff: 0.2 },
+        ] {
+            let sim = simulation(
+                LatticeKind::SimpleCubic,
+                Integrator::VelocityVerlet,
+                damping,
+            );
+            let error = max_energy_error(sim);
+            assert!(
+                error < 0.005,
+                "{damping:?} lost {error} more than it dissipated"
+            );
+        }
+    }
+
+    /// Energy error of a free lattice dropped on a ground with the given contact, and the work
+    /// the contact removed
+    fn drop_on_ground(contact: ContactModel) -> (f32, f32) {
+        let mut params = test_params(UVec3::splat(3), LatticeKind::SimpleCubic);
+        params.boundary_conditions = BoundaryConditions::default();
+        let mut sim = simulation_of(Lattice::generate(&params, &mut StdRng::seed_from_u64(2)));
+        sim.set_environment(
+            Vec3::new(0.0, -9.81, 0.0),
+            Some(Ground {
+                height: -0.5,
+                contact,
+                restitution: 0.5,
+                friction: 0.3,
+            }),
+        );
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let start = sim.energy.total;
+        let mut max_error: f32 = 0.0;
+        for _ in 0..STEPS {
+            sim.step(TIME_STEP, &mut rng);
+            let error = sim.energy.total + sim.energy.dissipated_work - start;
+            max_error = max_error.max(error.abs());
+        }
+        (max_error / start, sim.energy.dissipated_work)
+    }
+
+    #[test]
+    fn ground_impacts_account_for_the_lost_energy() {
+        let (error, dissipated) = drop_on_ground(ContactModel::Impulse);
+        assert!(dissipated > 0.0, "the lattice never hit the ground");
+        assert!(
+            error < 0.01,
+            "impacts lost {error} more than they dissipated"
+        );
+    }
+
+    #[test]
+    fn penalty_contact_accounts_for_the_lost_energy() {
+        let (error, dissipated) = drop_on_ground(ContactModel::Penalty { stiffness: 500.0 });
+        assert!(dissipated > 0.0, "the lattice never hit the ground");
+        // The contact switching on and off between force evaluations costs some accuracy
+        assert!(
+            error < 0.02,
+            "the contact lost {error} more than it dissipated"
+        );
+    }
+
+    #[test]
+    fn any_thread_count_gives_the_same_run() {
+        let run = |threads| {
+            let params = test_params(UVec3::splat(8), LatticeKind::FaceCenteredCubic);
+            let lattice = Lattice::generate(&params, &mut StdRng::seed_from_u64(5));
+            let thermostat = Thermostat::new(
+                ThermostatModel::Langevin { friction: 0.5 },
+                TemperatureSchedule(vec![(0.0, 1.0)]),
+            );
+            let mut sim = Simulation::new(
+                lattice,
+                Integrator::RungeKutta4,
+                DampingModel::BondDashpot,
+                thermostat,
+                threads,
+            );
+            let mut rng = StdRng::seed_from_u64(6);
+            for _ in 0..50 {
+                sim.step(TIME_STEP, &mut rng);
+            }
+            let pos: Vec<[u32; 3]> = sim
+                .lattice
+                .nodes
+                .pos
+                .iter()
+                .map(|pos| pos.to_array().map(f32::to_bits))
+                .collect();
+            (pos, sim.virial.to_cols_array().map(f32::to_bits))
+        };
+
+        let serial = run(1);
+        assert_eq!(serial, run(4), "4 threads");
+        assert_eq!(serial, run(0), "a thread per core");
+    }
+
+    #[test]
+    fn temperature_counts_only_free_directions() {
+        let mut params = test_params(UVec3::splat(3), LatticeKind::SimpleCubic);
+        // Corners come first so the 4 on the bottom face stay fixed
+        params.boundary_conditions.0.push(BoundaryCondition {
+            nodes: NodeSelector::Face(Face::MinY),
+            anchor: Anchor::Partial(Constraint::FixedAxes(glam::BVec3::new(false, true, false))),
+        });
+        let mut sim = simulation_of(Lattice::generate(&params, &mut StdRng::seed_from_u64(1)));
+        sim.lattice.nodes.vel.fill(Vec3::ONE);
+
+        // 64 nodes, 8 fixed corners and 12 more on the bottom face that can't move vertically
+        assert_eq!(sim.degrees_of_freedom(), 3 * 44 + 2 * 12);
+        // Only the 56 nodes that aren't fixed carry kinetic energy, 3/2 J each
+        let expected = 2.0 * 56.0 * 1.5 / 156.0;
+        assert!((sim.temperature() - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn stress_of_a_stretched_link() {
+        let dims = UVec3::ONE;
+        let mut nodes = Nodes::with_capacity(8);
+        for idx in 0..8 {
+            nodes.push(Vec3::new(0.0, idx as f32, 0.0), Vec3::ZERO, 1.0, None);
+        }
+        // Node 1 is pulled 0.2 m along x and z from its neighbour, stretching their link
+        nodes.pos[1] = Vec3::new(1.2, 0.0, 0.2);
+        let link = Link::new(10.0, Default::default(), 0.0, None, 1.0);
+        let links = Links::new(8, vec![(0, 1, link.clone())]);
+        let lattice =
+            Lattice::from_parts(dims, LatticeKind::SimpleCubic, 2.0, nodes, links, vec![]);
+        let sim = simulation_of(lattice);
+
+        let delta_pos = sim.lattice.nodes.pos[1] - sim.lattice.nodes.pos[0];
+        let force = link.force(delta_pos.length()) * delta_pos.normalize();
+        let expected = -outer(delta_pos, force) / 8.0;
+        let stress = sim.stress();
+        assert!(
+            stress.abs_diff_eq(expected, 1e-5),
+            "stress {stress} expected {expected}"
+        );
+        // A stretched link is in tension
+        assert!(stress.x_axis.x > 0.0 && sim.pressure() < 0.0);
+    }
+}
@@ -0,0 +1,315 @@
+use glam::Vec3;
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::node::Nodes;
+use crate::velocity::standard_normal;
+
+//-------------------------------------------------------
+// STRUCTS
+//-------------------------------------------------------
+
+/// How the temperature of the lattice is held at the target.
+/// Select it through the thermostat config. Temperatures are in energy units, J, as if
+/// Boltzmann's constant was 1.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThermostatModel {
+    /// The temperature is left alone
+    #[default]
+    None,
+    /// Drag of friction * m * v on every node plus a random force that balances it at the target.
+    /// Friction is in 1/s.
+    Langevin { friction: f32 },
+    /// Scales the velocities toward the target, relaxing with the time constant in s.
+    /// Quick to settle but doesn't give the right fluctuations.
+    Berendsen { time_constant: f32 },
+    /// Chain of Nosé–Hoover thermostats, each one controlling the one before it.
+    /// The time constant, s, sets how fast the first one reacts.
+    NoseHooverChain {
+        time_constant: f32,
+        chain_length: usize,
+    },
+}
+
+/// Target temperature over simulated time as (time, temperature) points.
+/// Linear between the points and held at the first and last ones outside them.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TemperatureSchedule(pub Vec<(f32, f32)>);
+
+/// A thermostat along with its state. Adds a force to every node that isn't static
+/// right after the link forces are summed up.
+#[derive(Clone, Debug, Default)]
+pub struct Thermostat {
+    pub model: ThermostatModel,
+    pub target: TemperatureSchedule,
+    /// Friction of every thermostat in the Nosé–Hoover chain, 1/s
+    chain: Vec<f32>,
+    /// Friction on every node for the current step, 1/s
+    friction: f32,
+    /// Random force on every node for the current step, indexed the same as the nodes
+    noise: Vec<Vec3>,
+}
+
+//-------------------------------------------------------
+// IMPLEMENTATIONS
+//-------------------------------------------------------
+
+impl Default for TemperatureSchedule {
+    fn default() -> Self {
+        TemperatureSchedule(vec![(0.0, 0.0)])
+    }
+}
+
+impl TemperatureSchedule {
+    /// Target temperature at the given time, J
+    pub fn at(&self, time: f64) -> f32 {
+        let time = time as f32;
+        let points = &self.0;
+        let Some(first) = points.first() else {
+            return 0.0;
+        };
+        if time <= first.0 {
+            return first.1;
+        }
+        for pair in points.windows(2) {
+            let ((t0, temp0), (t1, temp1)) = (pair[0], pair[1]);
+            if time <= t1 {
+                // Two points at the same time make a jump
+                return match t1 > t0 {
+                    true => temp0 + (temp1 - temp0) * (time - t0) / (t1 - t0),
+                    false => temp1,
+                };
+            }
+        }
+        points[points.len() - 1].1
+    }
+
+    /// Highest target temperature of the schedule, J
+    pub fn max(&self) -> f32 {
+        self.0.iter().map(|(_, temp)| *temp).fold(0.0, f32::max)
+    }
+}
+
+impl Thermostat {
+    /// Create a thermostat holding the lattice at the target.
+    /// Panics if the model's constants or the target temperatures can't be used with each other.
+    pub fn new(model: ThermostatModel, target: TemperatureSchedule) -> Self {
+        let usable = |value: f32| value.is_finite() && value > 0.0;
+        assert!(
+            target
+                .0
+                .iter()
+                .all(|(time, temp)| time.is_finite() && temp.is_finite() && *temp >= 0.0),
+            "thermostat target temperatures must be finite and not negative"
+        );
+        let chain_length = match model {
+            ThermostatModel::None => 0,
+            ThermostatModel::Langevin { friction } => {
+                assert!(
+                    friction.is_finite() && friction >= 0.0,
+                    "langevin friction must be finite and not negative"
+                );
+                0
+            }
+            ThermostatModel::Berendsen { time_constant } => {
+                assert!(
+                    usable(time_constant),
+                    "berendsen time constant must be positive"
+                );
+                0
+            }
+            ThermostatModel::NoseHooverChain {
+                time_constant,
+                chain_length,
+            } => {
+                // The masses of the chain scale with the target so it can't be 0 everywhere
+                assert!(
+                    usable(time_constant),
+                    "nose-hoover time constant must be positive"
+                );
+                assert!(
+                    chain_length >= 1,
+                    "nose-hoover chain needs at least one thermostat"
+                );
+                assert!(
+                    usable(target.max()),
+                    "nose-hoover chain needs a target temperature above 0"
+                );
+                chain_length
+            }
+        };
+        Thermostat {
+            model,
+            target,
+            chain: vec![0.0; chain_length],
+            friction: 0.0,
+            noise: Vec::new(),
+        }
+    }
+
+    /// Friction of every thermostat in the Nosé–Hoover chain, 1/s. Empty for the other models.
+    pub fn chain(&self) -> &[f32] {
+        &self.chain
+    }
+
+    /// Carry on a Nosé–Hoover chain from saved frictions, ignored if the length doesn't match
+    pub fn set_chain(&mut self, chain: &[f32]) {
+        if chain.len() == self.chain.len() {
+            self.chain.copy_from_slice(chain);
+        }
+    }
+
+    /// Work out the friction and random forces for the step starting at the given time.
+    /// They stay the same for every force evaluation of the step.
+    pub(crate) fn begin_step(
+        &mut self,
+        nodes: &Nodes,
+        time: f64,
+        delta_t: f32,
+        rng: &mut impl Rng,
+    ) {
+        let target = self.target.at(time);
+        match self.model {
+            ThermostatModel::None => self.friction = 0.0,
+            ThermostatModel::Langevin { friction } => {
+                self.friction = friction;
+                // Fluctuation-dissipation, the noise puts back what the friction takes on average
+                self.noise.clear();
+                self.noise.extend((0..nodes.len()).map(|idx| {
+                    if nodes.is_static(idx) {
+                        return Vec3::ZERO;
+                    }
+                    let std_dev = (2.0 * friction * nodes.mass[idx] * target / delta_t).sqrt();
+                    Vec3::new(
+                        standard_normal(rng),
+                        standard_normal(rng),
+                        standard_normal(rng),
+                    ) * std_dev
+                }));
+            }
+            ThermostatModel::Berendsen { time_constant } => {
+                // Friction that scales the velocities by lambda over the step
+                let temperature = nodes.temperature();
+                let lambda = match temperature > 0.0 {
+                    true => (1.0 + delta_t / time_constant * (target / temperature - 1.0))
+                        .max(0.0)
+                        .sqrt(),
+                    false => 1.0,
+                };
+                self.friction = (1.0 - lambda) / delta_t;
+            }
+            ThermostatModel::NoseHooverChain { time_constant, .. } => {
+                let dof = nodes.total_degrees_of_freedom();
+                if dof == 0 {
+                    self.friction = 0.0;
+                    return;
+                }
+                // Masses are fixed for the run so they are set from the hottest target
+                let temperature = nodes.temperature();
+                let mass = self.target.max() * time_constant * time_constant;
+                let first_mass = dof as f32 * mass;
+
+                let mass_of = |link: usize| match link {
+                    0 => first_mass,
+                    _ => mass,
+                };
+
+                // Each thermostat is driven by the kinetic energy of the one before it
+                let chain = &mut self.chain;
+                let mut rates = vec![0.0; chain.len()];
+                for link in 0..chain.len() {
+                    let drive = match link {
+                        0 => (dof as f32 * (temperature - target)) / first_mass,
+                        _ => {
+                            let prev = chain[link - 1];
+                            (mass_of(link - 1) * prev * prev - target) / mass_of(link)
+                        }
+                    };
+                    let next = chain.get(link + 1).copied().unwrap_or(0.0);
+                    rates[link] = drive - chain[link] * next;
+                }
+                for (friction, rate) in chain.iter_mut().zip(rates) {
+                    *friction += rate * delta_t;
+                }
+                self.friction = chain.first().copied().unwrap_or(0.0);
+            }
+        }
+    }
+
+    /// Force of the thermostat on a node that isn't static, N
+    pub(crate) fn force(&self, idx: usize, mass: f32, vel: Vec3) -> Vec3 {
+        let noise = self.noise.get(idx).copied().unwrap_or(Vec3::ZERO);
+        noise - self.friction * mass * vel
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::damping::DampingModel;
+    use crate::integrator::Integrator;
+    use crate::lattice::{test_params, Lattice};
+    use crate::simulation::Simulation;
+    use crate::velocity::VelocityInit;
+    use glam::UVec3;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    /// Mean kinetic temperature over the second half of a run with the given thermostat
+    fn mean_temperature(model: ThermostatModel, target: f32, steps: usize) -> f32 {
+        let mut params = test_params(UVec3::splat(3), crate::crystal::LatticeKind::SimpleCubic);
+        params.velocity_init = VelocityInit::MaxwellBoltzmann {
+            temperature: target,
+        };
+        let mut rng = StdRng::seed_from_u64(7);
+        let lattice = Lattice::generate(&params, &mut rng);
+        let thermostat = Thermostat::new(model, TemperatureSchedule(vec![(0.0, target)]));
+        let mut sim = Simulation::new(
+            lattice,
+            Integrator::VelocityVerlet,
+            DampingModel::None,
+            thermostat,
+            1,
+        );
+
+        let mut sum = 0.0;
+        for step in 0..steps {
+            sim.step(0.01, &mut rng);
+            if step >= steps / 2 {
+                sum += sim.temperature() as f64;
+            }
+        }
+        (sum / (steps - steps / 2) as f64) as f32
+    }
+
+    #[test]
+    fn nose_hoover_chain_holds_the_target() {
+        for time_constant in [0.3, 1.0] {
+            let model = ThermostatModel::NoseHooverChain {
+                time_constant,
+                chain_length: 3,
+            };
+            let mean = mean_temperature(model, 0.5, 20_000);
+            assert!(
+                (mean - 0.5).abs() < 0.02,
+                "mean temperature {mean} with time constant {time_constant}"
+            );
+        }
+    }
+
+    #[test]
+    fn langevin_holds_the_target() {
+        let mean = mean_temperature(ThermostatModel::Langevin { friction: 1.0 }, 0.5, 20_000);
+        assert!((mean - 0.5).abs() < 0.02, "mean temperature {mean}");
+    }
+
+    #[test]
+    #[should_panic(expected = "target temperature above 0")]
+    fn nose_hoover_chain_rejects_a_zero_target() {
+        let model = ThermostatModel::NoseHooverChain {
+            time_constant: 0.5,
+            chain_length: 3,
+        };
+        Thermostat::new(model, TemperatureSchedule(vec![(0.0, 0.0)]));
+    }
+}
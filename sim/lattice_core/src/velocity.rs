@@ -0,0 +1,121 @@
+use glam::{DMat3, DVec3, Vec3};
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::boundary::Anchor;
+use crate::node::Nodes;
+
+//-------------------------------------------------------
+// STRUCTS
+//-------------------------------------------------------
+
+/// How the starting velocities of the nodes are drawn.
+/// Select it through the lattice config.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VelocityInit {
+    /// Every component uniformly distributed between the lattice's start_vel_min and start_vel_max
+    #[default]
+    Uniform,
+    /// Maxwell-Boltzmann distribution at a temperature given in energy units, J, as if
+    /// Boltzmann's constant was 1. The net linear and angular momentum of the moving nodes
+    /// is removed and the velocities are scaled so the kinetic temperature is exactly the target.
+    MaxwellBoltzmann { temperature: f32 },
+}
+
+//-------------------------------------------------------
+// IMPLEMENTATIONS
+//-------------------------------------------------------
+
+/// Draw a velocity from the Maxwell-Boltzmann distribution for a node of the given mass
+pub(crate) fn maxwell_boltzmann(rng: &mut impl Rng, temperature: f32, mass: f32) -> Vec3 {
+    let std_dev = (temperature / mass).sqrt();
+    Vec3::new(
+        standard_normal(rng),
+        standard_normal(rng),
+        standard_normal(rng),
+    ) * std_dev
+}
+
+/// Draw from the normal distribution with mean 0 and standard deviation 1, Box-Muller transform
+pub(crate) fn standard_normal(rng: &mut impl Rng) -> f32 {
+    // 1 - x keeps the log away from 0
+    let radius = (-2.0 * (1.0 - rng.gen::<f64>()).ln()).sqrt();
+    let angle = std::f64::consts::TAU * rng.gen::<f64>();
+    (radius * angle.cos()) as f32
+}
+
+/// Remove the net linear and angular momentum of every node that isn't static, then scale
+/// the velocities so the kinetic temperature, 2 KE / DOF, is exactly the target.
+/// Partially constrained nodes only keep the part of the correction they are free to move along,
+/// so the momentum is only exactly zero when there are none.
+pub(crate) fn thermalize(nodes: &mut Nodes, temperature: f32) {
+    let moving: Vec<usize> = (0..nodes.len())
+        .filter(|idx| !nodes.is_static(*idx))
+        .collect();
+    if moving.is_empty() {
+        return;
+    }
+
+    // Sums are done in f64, the momenta largely cancel
+    let mass = |idx: usize| nodes.mass[idx] as f64;
+    let total_mass: f64 = moving.iter().map(|idx| mass(*idx)).sum();
+    let center_of_mass = moving
+        .iter()
+        .map(|idx| mass(*idx) * nodes.pos[*idx].as_dvec3())
+        .sum::<DVec3>()
+        / total_mass;
+
+    // Linear momentum
+    let momentum: DVec3 = moving
+        .iter()
+        .map(|idx| mass(*idx) * nodes.vel[*idx].as_dvec3())
+        .sum();
+    let drift = momentum / total_mass;
+
+    // Angular momentum about the center of mass, L = I w
+    let mut angular_momentum = DVec3::ZERO;
+    let mut inertia = DMat3::ZERO;
+    for idx in moving.iter() {
+        let r = nodes.pos[*idx].as_dvec3() - center_of_mass;
+        let v = nodes.vel[*idx].as_dvec3() - drift;
+        angular_momentum += mass(*idx) * r.cross(v);
+        inertia += mass(*idx) * (DMat3::from_diagonal(DVec3::splat(r.dot(r))) - outer(r, r));
+    }
+    // A single node or a line of nodes can't be spun, there is nothing to remove then
+    let spin = match inertia.determinant().abs() > f64::EPSILON {
+        true => inertia.inverse() * angular_momentum,
+        false => DVec3::ZERO,
+    };
+
+    for idx in moving.iter() {
+        let r = nodes.pos[*idx].as_dvec3() - center_of_mass;
+        let vel = (nodes.vel[*idx].as_dvec3() - drift - spin.cross(r)).as_vec3();
+        nodes.vel[*idx] = match nodes.anchor[*idx] {
+            Some(Anchor::Partial(constraint)) => constraint.project(vel),
+            _ => vel,
+        };
+    }
+
+    // Scaling keeps the momentum at zero
+    let kinetic_energy: f64 = moving
+        .iter()
+        .map(|idx| 0.5 * mass(*idx) * nodes.vel[*idx].as_dvec3().length_squared())
+        .sum();
+    let dof: u32 = moving
+        .iter()
+        .map(|idx| nodes.degrees_of_freedom(*idx))
+        .sum();
+    if kinetic_energy > 0.0 {
+        let target = 0.5 * dof as f64 * temperature as f64;
+        let scale = (target / kinetic_energy).sqrt() as f32;
+        for idx in moving.iter() {
+            nodes.vel[*idx] *= scale;
+        }
+    }
+}
+
+/// Outer product a b^T
+fn outer(a: DVec3, b: DVec3) -> DMat3 {
+    DMat3::from_cols(a * b.x, a * b.y, a * b.z)
+}
@@ -1,3 +1,18 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Deserializer};
+use std::{fmt, fs, io, path::Path, path::PathBuf};
+
+use crate::lattice::{
+    calc_extent, Anchor, BoundaryConditions, Constraint, ContactModel, DampingModel, Ground,
+    Integrator, LatticeKind, LatticeParams, LinkPotential, NodeSelector, Observable,
+    TelemetryFormat, TemperatureSchedule, ThermostatModel, TrajectoryFormat, VelocityInit,
+    MAX_BOND_SHELLS,
+};
+
+//-------------------------------------------------------
+// COMPILE TIME DEFAULTS
+//-------------------------------------------------------
+
 pub mod colors_config {
     use bevy::color::Srgba;
     use bevy::prelude::Color;
@@ -5,35 +20,54 @@ pub mod colors_config {
     pub const GREEN: Color = Color::Srgba(Srgba::GREEN);
     pub const RED: Color = Color::Srgba(Srgba::RED);
     pub const NODE_COLOR: Color = Color::WHITE;
-    pub const SPRING_COLOR: Color = Color::BLACK;
 }
 
 pub mod lattice_config {
-    use bevy::prelude::Visibility;
+    use crate::lattice::{
+        Anchor, BoundaryCondition, DampingModel, Integrator, LatticeKind, LinkPotential,
+        NodeSelector, VelocityInit,
+    };
+    use bevy::prelude::{UVec3, Visibility};
 
-    pub const DIM: u32 = 7; // TODO: CAN YOU INTENTIONALLY PARALLEIZE THE QUERIES FOR THE UPDATE?
+    // Number of unit cells along x, y and z
+    pub const DIMS: UVec3 = UVec3::new(7, 7, 7);
+    pub const LATTICE_KIND: LatticeKind = LatticeKind::SimpleCubic;
     pub const LINK_RADIUS: f32 = 0.05; //x and y component of cuboid get this
     pub const STARTING_LINK_LEN: f32 = 1.; // z component of cuboid gets this
     pub const LINK_VISIBILITY: Visibility = Visibility::Visible;
+    pub const LINK_COLOR_STEPS: u32 = 8; // levels per channel of the link gradient, one material each
+
+    pub const TIME_STEP: f32 = 0.01; // seconds per fixed update
+    pub const INTEGRATOR: Integrator = Integrator::SemiImplicitEuler;
+    pub const THREADS: usize = 0; // threads for the link forces, 0 uses one per core
+    pub const DAMPING_MODEL: DampingModel = DampingModel::BondDashpot;
 
     pub const NODE_RADIUS: f32 = LINK_RADIUS / 2.0;
     pub const NODE_MASS: f32 = 5.0;
 
-    pub const SPRING_CONST: f32 = 3.0;
+    // Spring constant of each neighbour shell that is bonded, nearest first, 1 to 3 shells
+    pub const BOND_SHELLS: &[f32] = &[1.5, 1.5];
+    // Angle links on straight and square nearest neighbour bond pairs, J/rad^2. 0 creates none
+    pub const BENDING_STIFFNESS: f32 = 0.0;
+    pub const LINK_POTENTIAL: LinkPotential = LinkPotential::Harmonic;
+    pub const DAMPING_COEFF: f32 = 0.25; // dashpot along each link, critical is around 2*sqrt(k*m)
+    pub const BREAKING_STRAIN: f32 = 1.0; // inf for links that never break
 
+    // Earlier conditions win when more than one selects a node
+    pub const BOUNDARY_CONDITIONS: &[BoundaryCondition] = &[BoundaryCondition {
+        nodes: NodeSelector::Corners,
+        anchor: Anchor::Fixed,
+    }];
+
+    // Uniform uses the range below, MaxwellBoltzmann { temperature } draws at a temperature in J (k_B = 1)
+    pub const VELOCITY_INIT: VelocityInit = VelocityInit::Uniform;
     const START_VEL_ABS: f32 = 5.0;
     pub const START_VEL_MIN: f32 = -START_VEL_ABS;
     pub const START_VEL_MAX: f32 = START_VEL_ABS;
 }
 
 pub mod lights_config {
-    use bevy::math::Vec3;
-
     pub const SPOT_LIGHT_SHADOWS: bool = false;
-
-    pub const POS_COMPONENT: f32 = 10.0;
-    pub const POS: Vec3 = Vec3::splat(POS_COMPONENT);
-    pub const POS_2: Vec3 = Vec3::new(-7.2, -10.4, -8.8);
 }
 
 pub mod cam_config {
@@ -41,9 +75,741 @@ pub mod cam_config {
     pub const POS: Vec3 = Vec3::new(-10., 11., -10.0);
 }
 
+pub mod headless_config {
+    // Run length when no simulated time is given
+    pub const STEPS: u64 = 10_000;
+    pub const OUTPUT_DIR: &str = "output";
+}
+
+pub mod trajectory_config {
+    use crate::lattice::TrajectoryFormat;
+
+    pub const EVERY: u64 = 0; // fixed steps between frames, 0 turns the trajectory off
+    pub const FORMATS: &[TrajectoryFormat] =
+        &[TrajectoryFormat::ExtendedXyz, TrajectoryFormat::LammpsDump];
+    pub const OUTPUT_DIR: &str = "output";
+}
+
+pub mod vtk_config {
+    pub const EVERY: u64 = 0; // fixed steps between frames, 0 turns the VTK output off
+    pub const OUTPUT_DIR: &str = "output";
+}
+
+pub mod checkpoint_config {
+    pub const EVERY: u64 = 0; // fixed steps between checkpoints, 0 turns them off
+    pub const OUTPUT_DIR: &str = "output";
+}
+
+pub mod telemetry_config {
+    use crate::lattice::{Observable, TelemetryFormat};
+
+    pub const EVERY: u64 = 0; // fixed steps between rows, 0 turns the telemetry off
+    pub const FORMAT: TelemetryFormat = TelemetryFormat::Csv;
+    pub const OBSERVABLES: &[Observable] = &[
+        Observable::KineticEnergy,
+        Observable::PotentialEnergy,
+        Observable::TotalEnergy,
+        Observable::Temperature,
+        Observable::CenterOfMass,
+        Observable::Momentum,
+        Observable::MaxStrain,
+    ];
+    pub const OUTPUT_DIR: &str = "output";
+}
+
+pub mod random_config {
+    // Seed of every random stream, none to draw one from the OS
+    pub const SEED: Option<u64> = None;
+}
+
+pub mod thermostat_config {
+    use crate::lattice::ThermostatModel;
+
+    pub const MODEL: ThermostatModel = ThermostatModel::None;
+    // (time s, temperature J) points of the target, linear between them
+    pub const TARGET: &[(f32, f32)] = &[(0.0, 0.0)];
+}
+
+pub mod environment_config {
+    use crate::lattice::ContactModel;
+    use bevy::math::Vec3;
+
+    pub const GRAVITY: Vec3 = Vec3::ZERO; // m/s^2, y is up so earth is (0, -9.81, 0)
+    pub const GROUND: bool = false; // collision floor, also shows the ground mesh
+    pub const GROUND_HEIGHT: f32 = -4.0;
+    pub const CONTACT: ContactModel = ContactModel::Impulse;
+    pub const RESTITUTION: f32 = 0.5; // fraction of the impact speed a node bounces back with
+    pub const FRICTION: f32 = 0.5; // Coulomb coefficient
+}
+
 pub mod axis_config {
     pub const GIRTH: f32 = 0.05;
     pub const LENGTH: f32 = 2.;
     pub const HALF_LENGTH: f32 = LENGTH / 2.;
     pub const ORIGIN_SPHERE_RADIUS: f32 = GIRTH;
 }
+
+//-------------------------------------------------------
+// RUNTIME CONFIGURATION
+//-------------------------------------------------------
+
+/// Everything that can be set from a config file. Any value left out of the file
+/// keeps the compile time default from the modules above.
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimConfig {
+    pub lattice: LatticeConfig,
+    pub lights: LightsConfig,
+    pub camera: CamConfig,
+    pub colors: ColorsConfig,
+    pub headless: HeadlessConfig,
+    pub trajectory: TrajectoryConfig,
+    pub vtk: VtkConfig,
+    pub checkpoint: CheckpointConfig,
+    pub telemetry: TelemetryConfig,
+    pub random: RandomConfig,
+    pub thermostat: ThermostatConfig,
+    pub environment: EnvironmentConfig,
+}
+
+/// Lattice generation and physics parameters, see `lattice_config`
+#[derive(Resource, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct LatticeConfig {
+    pub dims: UVec3,
+    pub kind: LatticeKind,
+    pub link_radius: f32,
+    pub starting_link_len: f32,
+    pub links_visible: bool,
+    pub link_color_steps: u32,
+    pub time_step: f32,
+    pub integrator: Integrator,
+    pub threads: usize,
+    pub damping: DampingModel,
+    pub node_radius: f32,
+    pub node_mass: f32,
+    /// Spring constant of each neighbour shell that is bonded, nearest first
+    pub bond_shells: Vec<f32>,
+    pub bending_stiffness: f32,
+    pub link_potential: LinkPotential,
+    pub damping_coeff: f32,
+    pub breaking_strain: f32,
+    pub boundary_conditions: BoundaryConditions,
+    pub velocity_init: VelocityInit,
+    pub start_vel_min: f32,
+    pub start_vel_max: f32,
+}
+
+/// Lighting parameters, see `lights_config`
+#[derive(Resource, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct LightsConfig {
+    pub spot_light_shadows: bool,
+}
+
+/// Camera parameters, see `cam_config`
+#[derive(Resource, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct CamConfig {
+    pub pos: Vec3,
+}
+
+/// Colors, given as hex strings in the config file. See `colors_config`
+#[derive(Resource, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ColorsConfig {
+    #[serde(deserialize_with = "deserialize_hex_color")]
+    pub x_axis: Color,
+    #[serde(deserialize_with = "deserialize_hex_color")]
+    pub y_axis: Color,
+    #[serde(deserialize_with = "deserialize_hex_color")]
+    pub z_axis: Color,
+    #[serde(deserialize_with = "deserialize_hex_color")]
+    pub node_color: Color,
+}
+
+/// Run length and output of a headless batch run, see `headless_config`
+#[derive(Resource, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct HeadlessConfig {
+    /// Number of fixed steps to run
+    pub steps: u64,
+    /// Simulated time to run for in seconds, overrides steps when set
+    pub sim_time: Option<f32>,
+    /// Directory the results are written to, created if it doesn't exist
+    pub output_dir: PathBuf,
+}
+
+/// Node trajectory written from the fixed update, see `trajectory_config`
+#[derive(Resource, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrajectoryConfig {
+    /// Fixed steps between frames, 0 to not write a trajectory
+    pub every: u64,
+    /// Every format is written to its own file
+    pub formats: Vec<TrajectoryFormat>,
+    /// Directory the trajectory files are written to, created if it doesn't exist
+    pub output_dir: PathBuf,
+}
+
+/// Lattice written as a VTK time series from the fixed update, see `vtk_config`
+#[derive(Resource, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct VtkConfig {
+    /// Fixed steps between frames, 0 to not write any
+    pub every: u64,
+    /// Directory the frames and their collection are written to, created if it doesn't exist
+    pub output_dir: PathBuf,
+}
+
+/// Saving and restoring the full state of a run, see `checkpoint_config`
+#[derive(Resource, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct CheckpointConfig {
+    /// Fixed steps between checkpoints, 0 to not write any
+    pub every: u64,
+    /// Directory the checkpoints are written to, created if it doesn't exist
+    pub output_dir: PathBuf,
+    /// Checkpoint to carry on from instead of generating a new lattice.
+    /// The lattice shape and link constants come from the checkpoint, the rest of the config still applies.
+    pub restart_from: Option<PathBuf>,
+}
+
+/// Observables recorded against simulated time from the fixed update, see `telemetry_config`
+#[derive(Resource, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetryConfig {
+    /// Fixed steps between rows, 0 to not record anything
+    pub every: u64,
+    pub format: TelemetryFormat,
+    /// Recorded in the order given
+    pub observables: Vec<Observable>,
+    /// Directory the telemetry is written to, created if it doesn't exist
+    pub output_dir: PathBuf,
+}
+
+/// Seeding of the random source, see `random_config`
+#[derive(Resource, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct RandomConfig {
+    /// Seed of every random stream, drawn from the OS when not given. The seed used is
+    /// printed and written to metadata.json so any run can be repeated.
+    pub seed: Option<u64>,
+}
+
+/// Temperature control of the lattice, see `thermostat_config`
+#[derive(Resource, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThermostatConfig {
+    pub model: ThermostatModel,
+    /// Target temperature over simulated time, J
+    pub target: TemperatureSchedule,
+}
+
+/// Gravity and the ground the lattice can land on, see `environment_config`
+#[derive(Resource, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnvironmentConfig {
+    /// m/s^2
+    pub gravity: Vec3,
+    /// Put a floor under the lattice that the nodes collide with
+    pub ground: bool,
+    /// m
+    pub ground_height: f32,
+    pub contact: ContactModel,
+    /// Fraction of the impact speed a node bounces back with, 0 to 1
+    pub restitution: f32,
+    /// Coulomb friction coefficient
+    pub friction: f32,
+}
+
+/// Reasons a config file could not be used
+#[derive(Debug)]
+pub enum ConfigError {
+    Read {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    Invalid(Vec<String>),
+}
+
+//-------------------------------------------------------
+// IMPLEMENTATIONS
+//-------------------------------------------------------
+
+impl Default for LatticeConfig {
+    fn default() -> Self {
+        LatticeConfig {
+            dims: lattice_config::DIMS,
+            kind: lattice_config::LATTICE_KIND,
+            link_radius: lattice_config::LINK_RADIUS,
+            starting_link_len: lattice_config::STARTING_LINK_LEN,
+            links_visible: lattice_config::LINK_VISIBILITY != Visibility::Hidden,
+            link_color_steps: lattice_config::LINK_COLOR_STEPS,
+            time_step: lattice_config::TIME_STEP,
+            integrator: lattice_config::INTEGRATOR,
+            threads: lattice_config::THREADS,
+            damping: lattice_config::DAMPING_MODEL,
+            node_radius: lattice_config::NODE_RADIUS,
+            node_mass: lattice_config::NODE_MASS,
+            bond_shells: lattice_config::BOND_SHELLS.to_vec(),
+            bending_stiffness: lattice_config::BENDING_STIFFNESS,
+            link_potential: lattice_config::LINK_POTENTIAL,
+            damping_coeff: lattice_config::DAMPING_COEFF,
+            breaking_strain: lattice_config::BREAKING_STRAIN,
+            boundary_conditions: BoundaryConditions(lattice_config::BOUNDARY_CONDITIONS.to_vec()),
+            velocity_init: lattice_config::VELOCITY_INIT,
+            start_vel_min: lattice_config::START_VEL_MIN,
+            start_vel_max: lattice_config::START_VEL_MAX,
+        }
+    }
+}
+
+impl Default for LightsConfig {
+    fn default() -> Self {
+        LightsConfig {
+            spot_light_shadows: lights_config::SPOT_LIGHT_SHADOWS,
+        }
+    }
+}
+
+impl Default for CamConfig {
+    fn default() -> Self {
+        CamConfig {
+            pos: cam_config::POS,
+        }
+    }
+}
+
+impl Default for ColorsConfig {
+    fn default() -> Self {
+        ColorsConfig {
+            x_axis: colors_config::RED,
+            y_axis: colors_config::GREEN,
+            z_axis: colors_config::BLUE,
+            node_color: colors_config::NODE_COLOR,
+        }
+    }
+}
+
+impl Default for HeadlessConfig {
+    fn default() -> Self {
+        HeadlessConfig {
+            steps: headless_config::STEPS,
+            sim_time: None,
+            output_dir: PathBuf::from(headless_config::OUTPUT_DIR),
+        }
+    }
+}
+
+impl Default for TrajectoryConfig {
+    fn default() -> Self {
+        TrajectoryConfig {
+            every: trajectory_config::EVERY,
+            formats: trajectory_config::FORMATS.to_vec(),
+            output_dir: PathBuf::from(trajectory_config::OUTPUT_DIR),
+        }
+    }
+}
+
+impl Default for VtkConfig {
+    fn default() -> Self {
+        VtkConfig {
+            every: vtk_config::EVERY,
+            output_dir: PathBuf::from(vtk_config::OUTPUT_DIR),
+        }
+    }
+}
+
+impl Default for CheckpointConfig {
+    fn default() -> Self {
+        CheckpointConfig {
+            every: checkpoint_config::EVERY,
+            output_dir: PathBuf::from(checkpoint_config::OUTPUT_DIR),
+            restart_from: None,
+        }
+    }
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        TelemetryConfig {
+            every: telemetry_config::EVERY,
+            format: telemetry_config::FORMAT,
+            observables: telemetry_config::OBSERVABLES.to_vec(),
+            output_dir: PathBuf::from(telemetry_config::OUTPUT_DIR),
+        }
+    }
+}
+
+impl Default for RandomConfig {
+    fn default() -> Self {
+        RandomConfig {
+            seed: random_config::SEED,
+        }
+    }
+}
+
+impl Default for ThermostatConfig {
+    fn default() -> Self {
+        ThermostatConfig {
+            model: thermostat_config::MODEL,
+            target: TemperatureSchedule(thermostat_config::TARGET.to_vec()),
+        }
+    }
+}
+
+impl Default for EnvironmentConfig {
+    fn default() -> Self {
+        EnvironmentConfig {
+            gravity: environment_config::GRAVITY,
+            ground: environment_config::GROUND,
+            ground_height: environment_config::GROUND_HEIGHT,
+            contact: environment_config::CONTACT,
+            restitution: environment_config::RESTITUTION,
+            friction: environment_config::FRICTION,
+        }
+    }
+}
+
+impl LatticeConfig {
+    /// Link visibility to spawn the link meshes with
+    pub fn link_visibility(&self) -> Visibility {
+        match self.links_visible {
+            true => Visibility::Visible,
+            false => Visibility::Hidden,
+        }
+    }
+
+    /// Parameters to build the lattice with
+    pub fn lattice_params(&self) -> LatticeParams {
+        LatticeParams {
+            dims: self.dims,
+            kind: self.kind,
+            cell_len: self.starting_link_len,
+            node_mass: self.node_mass,
+            bond_shells: self.bond_shells.clone(),
+            bending_stiffness: self.bending_stiffness,
+            link_potential: self.link_potential,
+            damping_coeff: self.damping_coeff,
+            breaking_strain: Some(self.breaking_strain).filter(|strain| strain.is_finite()),
+            boundary_conditions: self.boundary_conditions.clone(),
+            velocity_init: self.velocity_init,
+            start_vel_min: self.start_vel_min,
+            start_vel_max: self.start_vel_max,
+        }
+    }
+
+    /// Far corner of the box around the starting node positions, the near one is the origin
+    pub fn extent(&self) -> Vec3 {
+        calc_extent(self.dims, self.kind, self.starting_link_len)
+    }
+
+    /// Collect a message for every value that doesn't make sense
+    fn validate(&self, errors: &mut Vec<String>) {
+        let mut check = |ok: bool, msg: &str| {
+            if !ok {
+                errors.push(format!("lattice.{msg}"));
+            }
+        };
+
+        check(
+            self.dims.min_element() > 0,
+            "dims must be at least 1 along every axis",
+        );
+        check(self.link_radius > 0.0, "link_radius must be positive");
+        check(
+            self.starting_link_len > 0.0,
+            "starting_link_len must be positive",
+        );
+        check(
+            (2..=64).contains(&self.link_color_steps),
+            "link_color_steps must be between 2 and 64",
+        );
+        check(self.time_step > 0.0, "time_step must be positive");
+        check(self.node_radius > 0.0, "node_radius must be positive");
+        check(self.node_mass > 0.0, "node_mass must be positive");
+        check(
+            (1..=MAX_BOND_SHELLS).contains(&self.bond_shells.len()),
+            &format!("bond_shells must have 1 to {MAX_BOND_SHELLS} spring constants"),
+        );
+        check(
+            self.bond_shells
+                .iter()
+                .all(|spring_const| *spring_const >= 0.0),
+            "bond_shells spring constants can't be negative",
+        );
+        check(
+            self.bending_stiffness >= 0.0,
+            "bending_stiffness can't be negative",
+        );
+        check(self.damping_coeff >= 0.0, "damping_coeff can't be negative");
+        check(
+            self.breaking_strain > 0.0,
+            "breaking_strain must be positive",
+        );
+        check(
+            self.start_vel_min <= self.start_vel_max,
+            "start_vel_min can't be larger than start_vel_max",
+        );
+
+        if let VelocityInit::MaxwellBoltzmann { temperature } = self.velocity_init {
+            check(
+                temperature >= 0.0,
+                "velocity_init maxwell_boltzmann temperature can't be negative",
+            );
+        }
+
+        match self.damping {
+            DampingModel::Rayleigh {
+                mass_coeff,
+                stiffness_coeff,
+            } => check(
+                mass_coeff >= 0.0 && stiffness_coeff >= 0.0,
+                "damping rayleigh coefficients can't be negative",
+            ),
+            DampingModel::ViscousDrag { coeff } => {
+                check(coeff >= 0.0, "damping viscous_drag coeff can't be negative")
+            }
+            DampingModel::None | DampingModel::BondDashpot => {}
+        }
+
+        match self.link_potential {
+            LinkPotential::Morse { well_depth, width } => check(
+                well_depth > 0.0 && width > 0.0,
+                "link_potential morse well_depth and width must be positive",
+            ),
+            LinkPotential::LennardJones { well_depth } => check(
+                well_depth > 0.0,
+                "link_potential lennard_jones well_depth must be positive",
+            ),
+            LinkPotential::Fene { max_extension } => check(
+                max_extension > 0.0,
+                "link_potential fene max_extension must be positive",
+            ),
+            LinkPotential::Harmonic | LinkPotential::CubicAnharmonic { .. } => {}
+        }
+
+        for (idx, condition) in self.boundary_conditions.0.iter().enumerate() {
+            if let NodeSelector::Plane { axis, .. } = condition.nodes {
+                check(
+                    axis < 3,
+                    &format!("boundary_conditions[{idx}] plane axis must be 0, 1 or 2"),
+                );
+            }
+            if let Anchor::Partial(Constraint::Roller { normal }) = condition.anchor {
+                check(
+                    normal.length_squared() > 0.0,
+                    &format!("boundary_conditions[{idx}] roller normal can't be zero"),
+                );
+            }
+        }
+    }
+}
+
+impl HeadlessConfig {
+    /// Number of fixed steps to run given the length of a step, at least 1
+    pub fn num_steps(&self, time_step: f32) -> u64 {
+        match self.sim_time {
+            // Rounded up so a sim time shorter than a step still takes one,
+            // less a little so rounding error in the division doesn't add a step
+            Some(sim_time) => (sim_time / time_step - 1e-3).ceil().max(1.0) as u64,
+            None => self.steps,
+        }
+    }
+
+    /// Collect a message for every value that doesn't make sense
+    fn validate(&self, errors: &mut Vec<String>) {
+        if self.steps == 0 {
+            errors.push("headless.steps must be at least 1".to_string());
+        }
+        if let Some(sim_time) = self.sim_time {
+            if sim_time <= 0.0 {
+                errors.push("headless.sim_time must be positive".to_string());
+            }
+        }
+    }
+}
+
+impl TrajectoryConfig {
+    /// Collect a message for every value that doesn't make sense
+    fn validate(&self, errors: &mut Vec<String>) {
+        if self.every > 0 && self.formats.is_empty() {
+            errors.push("trajectory.formats needs at least one format".to_string());
+        }
+    }
+}
+
+impl TelemetryConfig {
+    /// Collect a message for every value that doesn't make sense
+    fn validate(&self, errors: &mut Vec<String>) {
+        if self.every > 0 && self.observables.is_empty() {
+            errors.push("telemetry.observables needs at least one observable".to_string());
+        }
+    }
+}
+
+impl ThermostatConfig {
+    /// Collect a message for every value that doesn't make sense
+    fn validate(&self, errors: &mut Vec<String>) {
+        let mut check = |ok: bool, msg: &str| {
+            if !ok {
+                errors.push(format!("thermostat.{msg}"));
+            }
+        };
+
+        let target = &self.target.0;
+        check(!target.is_empty(), "target needs at least one point");
+        check(
+            target.iter().all(|(_, temp)| *temp >= 0.0),
+            "target temperatures can't be negative",
+        );
+        check(
+            target.windows(2).all(|pair| pair[0].0 <= pair[1].0),
+            "target times must not decrease",
+        );
+        match self.model {
+            ThermostatModel::None => {}
+            ThermostatModel::Langevin { friction } => {
+                check(friction >= 0.0, "langevin friction can't be negative")
+            }
+            ThermostatModel::Berendsen { time_constant } => check(
+                time_constant > 0.0,
+                "berendsen time_constant must be positive",
+            ),
+            ThermostatModel::NoseHooverChain {
+                time_constant,
+                chain_length,
+            } => {
+                check(
+                    time_constant > 0.0,
+                    "nose_hoover_chain time_constant must be positive",
+                );
+                check(
+                    chain_length >= 1,
+                    "nose_hoover_chain chain_length must be at least 1",
+                );
+                check(
+                    self.target.max() > 0.0,
+                    "nose_hoover_chain needs a target temperature above 0",
+                );
+            }
+        }
+    }
+}
+
+impl EnvironmentConfig {
+    /// The floor for the physics, none if there isn't one. Nodes rest on it with their surface,
+    /// not their centre, so it is raised by the node radius.
+    pub fn ground(&self, node_radius: f32) -> Option<Ground> {
+        self.ground.then_some(Ground {
+            height: self.ground_height + node_radius,
+            contact: self.contact,
+            restitution: self.restitution,
+            friction: self.friction,
+        })
+    }
+
+    /// Collect a message for every value that doesn't make sense
+    fn validate(&self, errors: &mut Vec<String>) {
+        let mut check = |ok: bool, msg: &str| {
+            if !ok {
+                errors.push(format!("environment.{msg}"));
+            }
+        };
+
+        check(self.gravity.is_finite(), "gravity must be finite");
+        check(
+            (0.0..=1.0).contains(&self.restitution),
+            "restitution must be between 0 and 1",
+        );
+        check(self.friction >= 0.0, "friction can't be negative");
+        if let ContactModel::Penalty { stiffness } = self.contact {
+            check(
+                stiffness > 0.0,
+                "contact penalty stiffness must be positive",
+            );
+        }
+    }
+}
+
+impl SimConfig {
+    /// Read a config from a toml file and check that its values make sense
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let text = fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let config: SimConfig = toml::from_str(&text).map_err(|source| ConfigError::Parse {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Check every value and report all of the problems at once
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut errors = Vec::new();
+        self.lattice.validate(&mut errors);
+        self.headless.validate(&mut errors);
+        self.trajectory.validate(&mut errors);
+        self.telemetry.validate(&mut errors);
+        self.thermostat.validate(&mut errors);
+        self.environment.validate(&mut errors);
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(ConfigError::Invalid(errors)),
+        }
+    }
+
+    /// Insert each part of the config as a resource
+    pub fn insert_resources(self, app: &mut App) {
+        app.insert_resource(self.lattice)
+            .insert_resource(self.lights)
+            .insert_resource(self.camera)
+            .insert_resource(self.colors)
+            .insert_resource(self.headless)
+            .insert_resource(self.trajectory)
+            .insert_resource(self.vtk)
+            .insert_resource(self.checkpoint)
+            .insert_resource(self.telemetry)
+            .insert_resource(self.random)
+            .insert_resource(self.thermostat)
+            .insert_resource(self.environment);
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read { path, source } => {
+                write!(f, "could not read config {}: {source}", path.display())
+            }
+            ConfigError::Parse { path, source } => {
+                write!(f, "could not parse config {}: {source}", path.display())
+            }
+            ConfigError::Invalid(errors) => {
+                writeln!(f, "invalid config values:")?;
+                for error in errors {
+                    writeln!(f, "  {error}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Read a color written as a hex string, for example "3b4a56"
+fn deserialize_hex_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+    let hex = String::deserialize(deserializer)?;
+    Srgba::hex(&hex)
+        .map(Color::Srgba)
+        .map_err(|err| serde::de::Error::custom(format!("invalid hex color {hex:?}: {err}")))
+}
@@ -1,28 +1,79 @@
 use bevy::{
-    color,
-    gizmos::config,
     prelude::*,
-    time::common_conditions::{on_timer, once_after_delay, repeating_after_delay},
+    time::common_conditions::{on_timer, once_after_delay},
 };
+use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
+mod assets;
+mod batch;
+mod checkpoint;
 mod components;
+mod fracture;
 mod lattice_gen;
-use crate::config::{colors_config, lattice_config};
-use components::{Link, Node, Static};
-use lattice_gen::{create_all_nodes, generate_lattice, LatticeGen, RandomSourcePlugin};
+mod metadata;
+mod telemetry;
+mod trajectory;
+mod vtk;
+use crate::config::{EnvironmentConfig, LatticeConfig, ThermostatConfig};
+use assets::setup_lattice_assets;
+use batch::{track_batch_run, BatchPlugin};
+use checkpoint::CheckpointPlugin;
+use components::{Link, Node};
+pub use fracture::LinkBroken;
+use fracture::{break_links, report_broken_links};
+pub use lattice_core::{
+    calc_extent, Anchor, BoundaryCondition, BoundaryConditions, Constraint, ContactModel,
+    DampingModel, Ground, Integrator, LatticeKind, LatticeParams, LinkPotential, NodeSelector,
+    Simulation, TemperatureSchedule, ThermostatModel, VelocityInit, MAX_BOND_SHELLS,
+};
+use lattice_gen::{
+    create_all_nodes, create_simulation, generate_lattice, LatticeGen, RandomSource,
+    RandomSourcePlugin,
+};
+use metadata::write_run_metadata;
+use telemetry::TelemetryPlugin;
+pub use telemetry::{Observable, TelemetryFormat};
+pub use trajectory::TrajectoryFormat;
+use trajectory::TrajectoryPlugin;
+use vtk::VtkPlugin;
 
 //-------------------------------------------------------
 // STRUCTS
 //-------------------------------------------------------
 
-#[derive(Resource)]
+/// The simulation of the lattice, the entities only mirror it.
+/// Inserted once the lattice is generated.
+#[derive(Resource, Deref, DerefMut)]
+pub struct LatticeSimulation(pub Simulation);
+
+#[derive(Resource, Clone, Serialize, Deserialize)]
 pub struct SimulationData {
-    kinetic_energy: f32,
+    /// Kinetic energy of all non static nodes, J
+    pub kinetic_energy: f32,
+    /// Elastic energy stored in all links, J
+    pub potential_energy: f32,
+    /// Kinetic plus potential energy, J
+    pub total_energy: f32,
+    /// Running total of the work removed by damping since the start, J
+    pub dissipated_work: f32,
+    /// Kinetic temperature of all non static nodes, J
+    pub temperature: f32,
+    /// Minus the mean normal stress, Pa
+    pub pressure: f32,
+    /// Virial stress tensor, Pa. Positive is tension.
+    pub stress: Mat3,
     center_of_mass: Transform,
 }
 
-pub struct LatticePlugin;
+/// Generates and simulates the lattice described by the `LatticeConfig` resource.
+/// Falls back to the compile time defaults if the resource wasn't inserted before the plugin.
+#[derive(Default)]
+pub struct LatticePlugin {
+    /// Run without a window or renderer. No meshes are created and the run ends
+    /// after the length set in the `HeadlessConfig` resource.
+    pub headless: bool,
+}
 
 //-------------------------------------------------------
 // IMPLEMENTATIONS
@@ -32,6 +83,12 @@ impl Default for SimulationData {
     fn default() -> Self {
         SimulationData {
             kinetic_energy: 0.0,
+            potential_energy: 0.0,
+            total_energy: 0.0,
+            dissipated_work: 0.0,
+            temperature: 0.0,
+            pressure: 0.0,
+            stress: Mat3::ZERO,
             center_of_mass: Transform::from_translation(Vec3::ZERO),
         }
     }
@@ -44,26 +101,73 @@ impl Plugin for LatticePlugin {
         // Inserts the rng to generate the lattice
         app.add_plugins(RandomSourcePlugin);
 
-        app.insert_resource(Time::<Fixed>::from_duration(Duration::from_millis(10)));
+        app.init_resource::<LatticeConfig>();
+        app.init_resource::<ThermostatConfig>();
+        app.init_resource::<EnvironmentConfig>();
+        let time_step = app.world().resource::<LatticeConfig>().time_step;
+
+        app.insert_resource(Time::<Fixed>::from_seconds(time_step as f64));
         app.insert_resource(SimulationData::default());
-        app.insert_resource(LatticeGen::new(lattice_config::DIM));
+        app.add_event::<LinkBroken>();
+        app.init_resource::<LatticeGen>();
+        app.add_plugins((
+            TrajectoryPlugin,
+            VtkPlugin,
+            CheckpointPlugin,
+            TelemetryPlugin,
+        ));
+
+        if self.headless {
+            app.add_plugins(BatchPlugin);
+            // Nothing to look at so build the lattice right away
+            app.add_systems(
+                PostStartup,
+                (
+                    create_simulation,
+                    write_run_metadata,
+                    create_all_nodes,
+                    generate_lattice,
+                )
+                    .chain(),
+            );
+            app.add_systems(
+                FixedUpdate,
+                (update_nodes_state, break_links, track_batch_run)
+                    .chain()
+                    .run_if(resource_exists::<LatticeSimulation>),
+            );
+            app.add_systems(Update, report_broken_links);
+            return;
+        }
 
+        app.add_systems(Startup, setup_lattice_assets);
         app.add_systems(Update, rotate_around_center);
 
         app.add_systems(
             Update,
-            (create_all_nodes, generate_lattice)
+            (
+                create_simulation,
+                write_run_metadata,
+                create_all_nodes,
+                generate_lattice,
+            )
                 .chain()
                 .run_if(once_after_delay(LATTICE_START_DELAY)),
         );
         app.add_systems(
             FixedUpdate,
-            (update_nodes_state, update_link_physics, update_spring)
+            (update_nodes_state, break_links)
                 .chain()
-                .run_if(repeating_after_delay(LATTICE_START_DELAY)),
+                .run_if(resource_exists::<LatticeSimulation>),
+        );
+        // The fixed steps of a frame all run before update, so the meshes only need to catch up once
+        app.add_systems(
+            Update,
+            (update_node_transforms, update_spring).run_if(resource_exists::<LatticeSimulation>),
         );
 
         app.add_systems(Update, update_center_of_mass);
+        app.add_systems(Update, report_broken_links);
         app.add_systems(
             Update,
             print_kinetic_energy.run_if(on_timer(Duration::from_secs_f32(0.5))),
@@ -118,21 +222,21 @@ fn rotate_around_center(
 
 /// Update the center of mass of the cube in simulation data
 /// Used for camera rotations
-pub fn update_center_of_mass(mut data: ResMut<SimulationData>, nodes: Query<&Node>) {
-    let mut accum = Vec3::ZERO;
-    let mut count = 0;
-
-    for node in nodes.iter() {
-        count += 1;
-        accum += node.pos;
-    }
-
+pub fn update_center_of_mass(
+    mut data: ResMut<SimulationData>,
+    sim: Option<Res<LatticeSimulation>>,
+) {
     // Guard against not yet having any nodes spawned
-    if count == 0 {
+    let Some(sim) = sim else {
+        return;
+    };
+    let pos = &sim.lattice.nodes.pos;
+    if pos.is_empty() {
         return;
     }
 
-    data.center_of_mass.translation = accum / count as f32;
+    let accum: Vec3 = pos.iter().sum();
+    data.center_of_mass.translation = accum / pos.len() as f32;
     // println!("initial COM is {}", data.center_of_mass.translation);
 }
 
@@ -141,107 +245,52 @@ pub fn print_kinetic_energy(sim_data: Res<SimulationData>) {
     println!("{}", sim_data.kinetic_energy);
 }
 
-/// Update the state of the nodes and their positions using the calculated force
+/// Advance the simulation one fixed step and record its energies
 pub fn update_nodes_state(
     time: Res<Time>,
-    mut nodes: Query<(&mut Node, &mut Transform), Without<Static>>,
+    mut sim: ResMut<LatticeSimulation>,
     mut sim_data: ResMut<SimulationData>,
+    mut rng: ResMut<RandomSource>,
 ) {
     let delta_t = time.delta_seconds();
     // println!("Elasped time is {}", delta_t);
-
-    let mut total_kinetic_energy = 0.0;
-
-    for (mut node, mut transform) in nodes.iter_mut() {
-        // update vel and pos
-        let acc = node.sum_forces / node.mass;
-        node.vel = node.vel + acc * delta_t;
-        node.pos = node.pos + node.vel * delta_t;
-
-        // zero out sum forces
-        node.sum_forces = Vec3::ZERO;
-
-        // calculate the total kinetic energy
-        let velocity_magnitude = node.vel.length();
-        total_kinetic_energy += 0.5 * node.mass * velocity_magnitude * velocity_magnitude;
-
-        // Update the nodes mesh transform
-        transform.translation = node.pos;
-    }
-
-    sim_data.kinetic_energy = total_kinetic_energy;
+    sim.step(delta_t, &mut rng.thermostat);
+
+    sim_data.kinetic_energy = sim.energy.kinetic;
+    sim_data.potential_energy = sim.energy.potential;
+    sim_data.total_energy = sim.energy.total;
+    sim_data.dissipated_work = sim.energy.dissipated_work;
+    sim_data.temperature = sim.temperature();
+    sim_data.stress = sim.stress();
+    sim_data.pressure = sim.pressure();
 }
 
-/// Update spring physics and sum up forces on each node
-pub fn update_link_physics(
-    time: Res<Time>,
-    mut links: Query<(&mut Link, &Handle<StandardMaterial>)>,
-    mut nodes: Query<(&mut Node, &mut Transform)>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+/// Update the node mesh transforms from the node positions.
+/// Runs once per frame no matter how many fixed steps the frame took.
+pub fn update_node_transforms(
+    sim: Res<LatticeSimulation>,
+    mut nodes: Query<(&Node, &mut Transform)>,
 ) {
-    let delta_t = time.delta_seconds();
-    const DAMPING: f32 = 5.0; // 30 at damping and vel at 20 is pretty cool and div spring displament by 5
-
-    for (mut link, mut material_handle) in links.iter_mut() {
-
-        let node_from = nodes.get(link.from).unwrap();
-        let node_to = nodes.get(link.to).unwrap();
-
-        let node_to_pos = node_to.0.pos;
-        let node_from_pos = node_from.0.pos;
-        let force_dir = (node_to_pos - node_from_pos).normalize();
-        let length = (node_to_pos - node_from_pos).length();
-        let spring_displacement = length - link.orig_length;
-
-        // velocity of the spring is change of spring displacement over time. v = delta x / delta t
-        let velocity = (spring_displacement - link.delta_spring_length_pre) / delta_t;
-        let damping_force = -DAMPING * velocity;
-        // let damping_force =0.0;
-        let total_force = -1. * link.spring_const * spring_displacement + damping_force;
-        // let force = -1. * link.spring_const * spring_displacement - (DAMPING * (node_to.0.vel - node_from.0.vel)); // THIS IS WRONG, works in sim but wrong physics wise
-        // link.delta_spring_length_pre = spring_displacement/5.0; // results in more appealing simulations
-        link.delta_spring_length_pre = spring_displacement / 5.0;
-        let from_force = -0.5 * total_force * force_dir;
-        let to_force = -from_force;
-
-        // https://github.com/bevyengine/bevy/discussions/8487
-        let material = materials.get_mut(material_handle).unwrap();
-        // Doing it by force the spring exerts
-        // if let Some(force_color) = from_force.try_normalize() {
-        //     let scaled_vec = force_color * 0.5 + Vec3::splat(0.5);
-        //     material.base_color = Color::srgb(scaled_vec.x, scaled_vec.y, scaled_vec.z);
-        // }
-        // Doing it by the velocity. Apply the gain to get the colors to show more when velocity is low
-        // let normalized_vel = f32::abs(velocity) / 255.0  * 40.0;
-        // material.base_color = Color::srgb(normalized_vel, 0.0,0.0);
-
-        // this force is applied in the axis colinear from node 1 to node 2
-        nodes.get_mut(link.from).unwrap().0.sum_forces += from_force;
-        nodes.get_mut(link.to).unwrap().0.sum_forces += to_force;
+    for (node, mut transform) in nodes.iter_mut() {
+        transform.translation = sim.lattice.nodes.pos[node.0];
     }
 }
 
-/// Update the spring transforms
+/// Update the spring transforms.
+/// Runs once per frame no matter how many fixed steps the frame took.
 pub fn update_spring(
+    sim: Res<LatticeSimulation>,
     mut links: Query<(&Link, &mut Transform), Without<Node>>,
-    nodes: Query<&Node>,
 ) {
     for (link, mut transform) in links.iter_mut() {
-        // expect and unwrap not encouraged to use, see
-        // https://doc.rust-lang.org/book/ch09-02-recoverable-errors-with-result.html
-        let node_to = nodes
-            .get(link.to)
-            .expect("The node should exist as no nodes are ever despawned.");
-        let node_from = nodes
-            .get(link.from)
-            .expect("The node should exist as no nodes are ever despawned.");
-
+        let to_pos = sim.lattice.nodes.pos[link.to];
+        let from_pos = sim.lattice.nodes.pos[link.from];
         // update position and length of the spring
         // USE POS, NOT THE TRANSFORM OF THE SPRING
         // this is because the transform only updates every frame where as pos updates every fixedtimestep
-        let dir = node_to.pos - node_from.pos;
-        transform.translation = dir / 2. + node_from.pos;
-        transform.scale.z = dir.length() / link.orig_length;
+        let dir = to_pos - from_pos;
+        transform.translation = dir / 2. + from_pos;
+        transform.scale.z = dir.length();
 
         // rotate the spring so it aligns with the direction vector between the two nodes (node_to - node_from)
         // best attempt at explaining
@@ -252,7 +301,7 @@ pub fn update_spring(
         // I want Z to point to the vector from node_from to node_to or the dir vector
         // The secondary axis - just using Y, I assume X would work, I want to point to a vector orthogonal to the dir vector.
         // I get this vector by crossing the direction vector node_from vector.
-        *transform = transform.aligned_by(Vec3::Z, dir, Vec3::Y, dir.cross(node_from.pos));
+        *transform = transform.aligned_by(Vec3::Z, dir, Vec3::Y, dir.cross(from_pos));
         // okay the cross thing is nice but i think that was causing a lot of them to spin which makes sense
         // because dir cross node_from trans could point in any outward direction really.
         // *transform = transform.aligned_by(Vec3::Z, dir, Vec3::Y, Vec3::splat(100.0));  //this seems to cause the sticks to glitch occasionally.
@@ -0,0 +1,73 @@
+use bevy::prelude::*;
+
+use crate::config::{ColorsConfig, LatticeConfig};
+
+//-------------------------------------------------------
+// STRUCTS
+//-------------------------------------------------------
+
+/// Meshes and materials shared by every node and link.
+/// Entities with the same mesh and material are drawn together in one batch,
+/// so every node and link uses a unit mesh scaled through its transform.
+#[derive(Resource)]
+pub struct LatticeAssets {
+    /// Sphere of radius 1
+    pub node_mesh: Handle<Mesh>,
+    pub node_material: Handle<StandardMaterial>,
+    /// Cube of side 1, z is stretched to the length of the link
+    pub link_mesh: Handle<Mesh>,
+    /// Colours of the link gradient, `palette_steps` levels per colour channel
+    link_palette: Vec<Handle<StandardMaterial>>,
+    palette_steps: u32,
+}
+
+//-------------------------------------------------------
+// IMPLEMENTATIONS
+//-------------------------------------------------------
+
+impl LatticeAssets {
+    /// Get the palette material closest to the colour, channels are clamped to 0..1
+    pub fn link_material(&self, color: Vec3) -> Handle<StandardMaterial> {
+        let steps = self.palette_steps;
+        let level = (color.clamp(Vec3::ZERO, Vec3::ONE) * (steps - 1) as f32)
+            .round()
+            .as_uvec3();
+        let idx = (level.z * steps + level.y) * steps + level.x;
+        self.link_palette[idx as usize].clone()
+    }
+}
+
+/// Add the shared meshes and materials. Only needed when there is something to render.
+pub fn setup_lattice_assets(
+    mut commands: Commands,
+    config: Res<LatticeConfig>,
+    colors: Res<ColorsConfig>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let steps = config.link_color_steps;
+    let max_level = (steps - 1) as f32;
+
+    // Same order as the lookup in link_material, x is the fastest changing
+    let mut link_palette = Vec::with_capacity(steps.pow(3) as usize);
+    for b in 0..steps {
+        for g in 0..steps {
+            for r in 0..steps {
+                let color = Color::srgb(
+                    r as f32 / max_level,
+                    g as f32 / max_level,
+                    b as f32 / max_level,
+                );
+                link_palette.push(materials.add(color));
+            }
+        }
+    }
+
+    commands.insert_resource(LatticeAssets {
+        node_mesh: meshes.add(Sphere::new(1.0).mesh().uv(32, 18)),
+        node_material: materials.add(colors.node_color),
+        link_mesh: meshes.add(Cuboid::new(1.0, 1.0, 1.0)),
+        link_palette,
+        palette_steps: steps,
+    });
+}
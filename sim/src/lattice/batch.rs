@@ -0,0 +1,171 @@
+use bevy::{app::AppExit, prelude::*, time::TimeUpdateStrategy};
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::Path,
+    time::Duration,
+};
+
+use crate::config::{HeadlessConfig, LatticeConfig};
+use crate::lattice::metadata::OutputDirs;
+use crate::lattice::{LatticeSimulation, Simulation, SimulationData};
+
+//-------------------------------------------------------
+// STRUCTS
+//-------------------------------------------------------
+
+/// Runs the lattice for the length given in the `HeadlessConfig` resource as fast as possible,
+/// then writes the results to its output directory and exits the app.
+/// Every update advances the simulation by exactly one fixed step.
+pub struct BatchPlugin;
+
+/// Progress of the batch run
+#[derive(Resource)]
+pub struct BatchRun {
+    /// Fixed steps taken so far in this run, a restarted run counts from 0
+    step: u64,
+    /// Fixed steps to take in total
+    num_steps: u64,
+    /// Energies after every step
+    energy_log: BufWriter<File>,
+    /// Set once the results are written and exit is sent
+    finished: bool,
+}
+
+//-------------------------------------------------------
+// IMPLEMENTATIONS
+//-------------------------------------------------------
+
+impl Plugin for BatchPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<HeadlessConfig>();
+        let headless = app.world().resource::<HeadlessConfig>().clone();
+        let time_step = app.world().resource::<LatticeConfig>().time_step;
+
+        // Step by simulated time instead of wall time so the run is not throttled.
+        // The virtual clock would otherwise clamp large time steps.
+        let step_duration = Duration::from_secs_f64(time_step as f64);
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(step_duration));
+        app.world_mut()
+            .resource_mut::<Time<Virtual>>()
+            .set_max_delta(step_duration.max(Duration::from_millis(250)));
+
+        fs::create_dir_all(&headless.output_dir).unwrap_or_else(|err| {
+            panic!(
+                "could not create output directory {}: {err}",
+                headless.output_dir.display()
+            )
+        });
+        OutputDirs::register(app, &headless.output_dir);
+        let mut energy_log = BufWriter::new(create_file(&headless.output_dir, "energy.csv"));
+        writeln!(
+            energy_log,
+            "step,time,kinetic_energy,potential_energy,total_energy,dissipated_work"
+        )
+        .unwrap();
+
+        let num_steps = headless.num_steps(time_step);
+        println!(
+            "Running {num_steps} steps headless, writing results to {}",
+            headless.output_dir.display()
+        );
+
+        app.insert_resource(BatchRun {
+            step: 0,
+            num_steps,
+            energy_log,
+            finished: false,
+        });
+    }
+}
+
+/// Create a file in the output directory, panics with the path if it can't
+fn create_file(output_dir: &Path, name: &str) -> File {
+    let path = output_dir.join(name);
+    File::create(&path).unwrap_or_else(|err| panic!("could not create {}: {err}", path.display()))
+}
+
+//-------------------------------------------------------
+// SYSTEMS
+//-------------------------------------------------------
+
+/// Record the energies of the step that just finished and end the run once every step is done.
+/// Must run after the physics in the fixed update.
+pub fn track_batch_run(
+    sim: Res<LatticeSimulation>,
+    sim_data: Res<SimulationData>,
+    headless: Res<HeadlessConfig>,
+    mut run: ResMut<BatchRun>,
+    mut exit: EventWriter<AppExit>,
+) {
+    // The app finishes the frame after exit is sent, don't keep stepping past the end
+    if run.finished {
+        return;
+    }
+
+    // A run of no steps still writes its results and exits
+    if run.step < run.num_steps {
+        run.step += 1;
+        writeln!(
+            run.energy_log,
+            "{},{},{},{},{},{}",
+            sim.steps,
+            sim.time,
+            sim_data.kinetic_energy,
+            sim_data.potential_energy,
+            sim_data.total_energy,
+            sim_data.dissipated_work
+        )
+        .unwrap();
+
+        if run.step < run.num_steps {
+            return;
+        }
+    }
+
+    run.energy_log.flush().unwrap();
+    write_nodes(&headless.output_dir, &sim);
+    write_links(&headless.output_dir, &sim);
+    println!(
+        "Finished {} steps, {} s of simulated time. Total energy is {}",
+        run.step, sim.time, sim_data.total_energy
+    );
+    run.finished = true;
+    exit.send(AppExit::Success);
+}
+
+/// Write the final state of every node, in lattice order
+fn write_nodes(output_dir: &Path, sim: &Simulation) {
+    let mut file = BufWriter::new(create_file(output_dir, "nodes.csv"));
+    writeln!(file, "index,x,y,z,vx,vy,vz,static").unwrap();
+    let nodes = &sim.lattice.nodes;
+    for idx in 0..nodes.len() {
+        let (pos, vel) = (nodes.pos[idx], nodes.vel[idx]);
+        writeln!(
+            file,
+            "{idx},{},{},{},{},{},{},{}",
+            pos.x,
+            pos.y,
+            pos.z,
+            vel.x,
+            vel.y,
+            vel.z,
+            nodes.is_static(idx) as u8
+        )
+        .unwrap();
+    }
+    file.flush().unwrap();
+}
+
+/// Write the final state of every link that is still intact.
+/// The ends are given by the index of their node in nodes.csv.
+fn write_links(output_dir: &Path, sim: &Simulation) {
+    let pos = &sim.lattice.nodes.pos;
+    let mut file = BufWriter::new(create_file(output_dir, "links.csv"));
+    writeln!(file, "from,to,length,strain").unwrap();
+    for (from, to, link) in sim.lattice.links.iter() {
+        let length = pos[from].distance(pos[to]);
+        writeln!(file, "{from},{to},{length},{}", link.strain(length)).unwrap();
+    }
+    file.flush().unwrap();
+}
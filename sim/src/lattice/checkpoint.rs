@@ -0,0 +1,308 @@
+use bevy::prelude::*;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::config::CheckpointConfig;
+use crate::lattice::fracture::break_links;
+use crate::lattice::lattice_gen::RandomSource;
+use crate::lattice::metadata::OutputDirs;
+use crate::lattice::{LatticeSimulation, SimulationData};
+use lattice_core::SimulationState;
+
+//-------------------------------------------------------
+// STRUCTS
+//-------------------------------------------------------
+
+/// Writes a checkpoint every given number of fixed steps, see `CheckpointConfig`.
+/// Restoring from one happens when the lattice is generated.
+pub struct CheckpointPlugin;
+
+/// The complete state of a run, written as json
+#[derive(Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Checkpoints from a different version can't be read
+    version: u32,
+    pub simulation: SimulationState,
+    pub rng: RandomSourceState,
+    pub sim_data: SimulationData,
+}
+
+/// Seed of the random source and where each of its streams is
+#[derive(Serialize, Deserialize)]
+pub struct RandomSourceState {
+    seed: u64,
+    velocities: RngState,
+    thermostat: RngState,
+    defects: RngState,
+}
+
+/// Reasons a checkpoint could not be used
+#[derive(Debug)]
+pub enum CheckpointError {
+    Read {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    Version {
+        path: PathBuf,
+        version: u32,
+    },
+}
+
+/// Where a random number generator is in its stream
+#[derive(Serialize, Deserialize)]
+struct RngState {
+    seed: [u8; 32],
+    stream: u64,
+    word_pos: u128,
+}
+
+//-------------------------------------------------------
+// IMPLEMENTATIONS
+//-------------------------------------------------------
+
+impl Checkpoint {
+    /// Bumped whenever the layout of a checkpoint changes
+    const VERSION: u32 = 3;
+
+    /// Take a copy of the state of the run
+    pub fn new(sim: &LatticeSimulation, rng: &RandomSource, sim_data: &SimulationData) -> Self {
+        Checkpoint {
+            version: Self::VERSION,
+            simulation: sim.state(),
+            rng: RandomSourceState::new(rng),
+            sim_data: sim_data.clone(),
+        }
+    }
+
+    /// Read a checkpoint
+    pub fn load(path: &Path) -> Result<Self, CheckpointError> {
+        let file = File::open(path).map_err(|source| CheckpointError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let checkpoint: Checkpoint =
+            serde_json::from_reader(BufReader::new(file)).map_err(|source| {
+                CheckpointError::Parse {
+                    path: path.to_path_buf(),
+                    source,
+                }
+            })?;
+        if checkpoint.version != Self::VERSION {
+            return Err(CheckpointError::Version {
+                path: path.to_path_buf(),
+                version: checkpoint.version,
+            });
+        }
+        Ok(checkpoint)
+    }
+
+    /// Write the checkpoint to a file
+    fn save(&self, path: &Path) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(&mut out, self)?;
+        out.flush()
+    }
+}
+
+impl RandomSourceState {
+    fn new(rng: &RandomSource) -> Self {
+        RandomSourceState {
+            seed: rng.seed,
+            velocities: RngState::new(&rng.velocities),
+            thermostat: RngState::new(&rng.thermostat),
+            defects: RngState::new(&rng.defects),
+        }
+    }
+
+    /// Rebuild the random source with every stream where it was
+    pub fn random_source(&self) -> RandomSource {
+        RandomSource {
+            seed: self.seed,
+            velocities: self.velocities.rng(),
+            thermostat: self.thermostat.rng(),
+            defects: self.defects.rng(),
+        }
+    }
+}
+
+impl RngState {
+    fn new(rng: &ChaCha8Rng) -> Self {
+        RngState {
+            seed: rng.get_seed(),
+            stream: rng.get_stream(),
+            word_pos: rng.get_word_pos(),
+        }
+    }
+
+    /// Rebuild the rng at the same point in its stream
+    fn rng(&self) -> ChaCha8Rng {
+        let mut rng = ChaCha8Rng::from_seed(self.seed);
+        rng.set_stream(self.stream);
+        rng.set_word_pos(self.word_pos);
+        rng
+    }
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointError::Read { path, source } => {
+                write!(f, "could not open checkpoint {}: {source}", path.display())
+            }
+            CheckpointError::Parse { path, source } => {
+                write!(f, "could not read checkpoint {}: {source}", path.display())
+            }
+            CheckpointError::Version { path, version } => write!(
+                f,
+                "checkpoint {} is version {version}, only version {} can be read",
+                path.display(),
+                Checkpoint::VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {}
+
+impl Plugin for CheckpointPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CheckpointConfig>();
+        let config = app.world().resource::<CheckpointConfig>().clone();
+        if config.every == 0 {
+            return;
+        }
+
+        fs::create_dir_all(&config.output_dir).unwrap_or_else(|err| {
+            panic!(
+                "could not create output directory {}: {err}",
+                config.output_dir.display()
+            )
+        });
+        OutputDirs::register(app, &config.output_dir);
+        println!(
+            "Writing a checkpoint every {} steps to {}",
+            config.every,
+            config.output_dir.display()
+        );
+
+        app.add_systems(
+            FixedUpdate,
+            write_checkpoint
+                .after(break_links)
+                .run_if(resource_exists::<LatticeSimulation>),
+        );
+    }
+}
+
+//-------------------------------------------------------
+// SYSTEMS
+//-------------------------------------------------------
+
+/// Save the state of the run when the step is on the interval.
+/// Files are named by the step so a run can be branched from any of them.
+fn write_checkpoint(
+    sim: Res<LatticeSimulation>,
+    rng: Res<RandomSource>,
+    sim_data: Res<SimulationData>,
+    config: Res<CheckpointConfig>,
+) {
+    if !sim.steps.is_multiple_of(config.every) {
+        return;
+    }
+
+    let path = config
+        .output_dir
+        .join(format!("checkpoint_{:06}.json", sim.steps));
+    Checkpoint::new(&sim, &rng, &sim_data)
+        .save(&path)
+        .unwrap_or_else(|err| panic!("could not write checkpoint {}: {err}", path.display()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lattice_core::{
+        DampingModel, Integrator, Lattice, LatticeParams, Simulation, TemperatureSchedule,
+        Thermostat, ThermostatModel,
+    };
+
+    use crate::config::LatticeConfig;
+
+    fn thermostat() -> Thermostat {
+        Thermostat::new(
+            ThermostatModel::Langevin { friction: 0.5 },
+            TemperatureSchedule(vec![(0.0, 1.0)]),
+        )
+    }
+
+    #[test]
+    fn restored_run_steps_identically() {
+        // Unbreakable links are the case that can't be written as a json number
+        let params = LatticeParams {
+            breaking_strain: None,
+            ..LatticeConfig::default().lattice_params()
+        };
+        let mut rng = RandomSource::new(3);
+        let lattice = Lattice::generate(&params, &mut rng.velocities);
+        let mut sim = LatticeSimulation(Simulation::new(
+            lattice,
+            Integrator::VelocityVerlet,
+            DampingModel::None,
+            thermostat(),
+            1,
+        ));
+        for _ in 0..10 {
+            sim.step(0.001, &mut rng.thermostat);
+        }
+
+        let path =
+            std::env::temp_dir().join(format!("checkpoint_test_{}.json", std::process::id()));
+        Checkpoint::new(&sim, &rng, &SimulationData::default())
+            .save(&path)
+            .unwrap();
+        let checkpoint = Checkpoint::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let mut restored_rng = checkpoint.rng.random_source();
+        let mut restored = Simulation::restore(
+            checkpoint.simulation,
+            Integrator::VelocityVerlet,
+            DampingModel::None,
+            thermostat(),
+            1,
+        );
+        for _ in 0..10 {
+            sim.step(0.001, &mut rng.thermostat);
+            restored.step(0.001, &mut restored_rng.thermostat);
+        }
+
+        let bits = |pos: &[Vec3]| -> Vec<[u32; 3]> {
+            pos.iter().map(|p| p.to_array().map(f32::to_bits)).collect()
+        };
+        assert_eq!(
+            bits(&sim.lattice.nodes.pos),
+            bits(&restored.lattice.nodes.pos)
+        );
+        assert_eq!(
+            bits(&sim.lattice.nodes.vel),
+            bits(&restored.lattice.nodes.vel)
+        );
+        assert!(restored
+            .lattice
+            .links
+            .iter()
+            .all(|(_, _, link)| link.breaking_strain.is_none()));
+    }
+}
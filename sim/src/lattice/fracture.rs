@@ -0,0 +1,71 @@
+use bevy::{prelude::*, utils::HashSet};
+
+use crate::lattice::components::AngleLink;
+use crate::lattice::lattice_gen::LatticeGen;
+use crate::lattice::LatticeSimulation;
+
+//-------------------------------------------------------
+// STRUCTS
+//-------------------------------------------------------
+
+/// Sent whenever a link is stretched past its breaking strain and removed
+#[derive(Event, Debug)]
+pub struct LinkBroken {
+    pub from: Entity,
+    pub to: Entity,
+    /// Strain of the link at the moment it failed
+    pub strain: f32,
+}
+
+//-------------------------------------------------------
+// SYSTEMS
+//-------------------------------------------------------
+
+/// Remove every link whose strain is past its breaking strain and despawn its entity,
+/// along with the entities of the angle links that bent around it.
+/// Only the link is removed, the nodes it joined stay in the lattice.
+pub fn break_links(
+    mut commands: Commands,
+    mut sim: ResMut<LatticeSimulation>,
+    mut lattice_gen: ResMut<LatticeGen>,
+    mut broken_links: EventWriter<LinkBroken>,
+    angles: Query<(Entity, &AngleLink)>,
+) {
+    let broken = sim.break_links();
+    if broken.is_empty() {
+        return;
+    }
+
+    let intact: HashSet<_> = sim
+        .lattice
+        .angles
+        .iter()
+        .map(|angle| (angle.vertex, angle.ends))
+        .collect();
+    for (entity, angle) in angles.iter() {
+        if !intact.contains(&(angle.vertex, angle.ends)) {
+            commands.entity(entity).despawn();
+        }
+    }
+
+    for broken in broken {
+        if let Some(entity) = lattice_gen.links.remove(&(broken.from, broken.to)) {
+            commands.entity(entity).despawn();
+        }
+        broken_links.send(LinkBroken {
+            from: lattice_gen.data[broken.from],
+            to: lattice_gen.data[broken.to],
+            strain: broken.strain,
+        });
+    }
+}
+
+/// Print every link that broke this frame
+pub fn report_broken_links(mut broken_links: EventReader<LinkBroken>) {
+    for broken in broken_links.read() {
+        println!(
+            "Link between {} and {} broke at strain {}",
+            broken.from, broken.to, broken.strain
+        );
+    }
+}
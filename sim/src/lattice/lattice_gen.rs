@@ -1,252 +1,191 @@
-use bevy::prelude::*;
-use rand::{distributions::Uniform, Rng, SeedableRng};
+use bevy::{prelude::*, utils::HashMap};
+use lattice_core::{Lattice, Simulation, Thermostat};
+use rand::{rngs::OsRng, RngCore, SeedableRng};
 use rand_chacha::ChaCha8Rng;
 
-use crate::config::{colors_config, lattice_config};
+use crate::config::{
+    CheckpointConfig, EnvironmentConfig, LatticeConfig, RandomConfig, ThermostatConfig,
+};
 
-use crate::lattice::components::{Link, Node, Static};
-
-// use crate::lattice::components
+use crate::lattice::assets::LatticeAssets;
+use crate::lattice::checkpoint::Checkpoint;
+use crate::lattice::components::{AngleLink, Link, Node};
+use crate::lattice::{LatticeSimulation, SimulationData};
 
 //-------------------------------------------------------
 // STRUCTS
 //-------------------------------------------------------
 
-/// A resource for used for generating random numbers for
-/// the lattice's starting velocities
+/// Random numbers for everything in the simulation that needs them.
+/// Every use gets its own stream of the same seed so drawing more numbers
+/// for one of them doesn't change the numbers the others get.
 #[derive(Resource)]
-pub struct RandomSource(pub ChaCha8Rng);
+pub struct RandomSource {
+    /// Seed every stream was started from
+    pub seed: u64,
+    /// The lattice's starting velocities
+    pub velocities: ChaCha8Rng,
+    /// Noise of the thermostat
+    pub thermostat: ChaCha8Rng,
+    /// Where defects are placed. Nothing draws from it yet, it is kept so placing defects
+    /// later doesn't change the runs of existing seeds.
+    pub defects: ChaCha8Rng,
+}
 
-/// A plugin for the random source
+/// A plugin for the random source. Seeded from the `RandomConfig` resource,
+/// or from the OS when it doesn't give a seed.
 pub struct RandomSourcePlugin;
 
-/// Data structure for holding all of the nodes for lattice generation.
-#[derive(Resource)]
+/// The entities mirroring the nodes and links of the simulation
+#[derive(Resource, Default)]
 pub struct LatticeGen {
-    /// Dim is the number of nodes along one side, NOT the number of 1x1x1 cubes along face
-    pub nodes_dim: u32,
-    /// A 1D array of all the node elements
+    /// Entity of every node, indexed the same as the nodes of the lattice
     pub data: Vec<Entity>,
+    /// Entity of every intact link, keyed by the indices of the nodes it comes from and goes to
+    pub links: HashMap<(usize, usize), Entity>,
 }
 
 //-------------------------------------------------------
 // LATTICE GENERATION FUNCTIONS
 //-------------------------------------------------------
 
-/// Get a vector of indices specifying which nodes should receive
-/// the static component during generation
-fn get_static_node_indices() -> Vec<(u32, u32, u32)> {
-    let corner_index = lattice_config::DIM;
-    vec![
-        (0, 0, 0),
-        (corner_index, corner_index, corner_index),
-        (corner_index, 0, 0),
-        (0, corner_index, 0),
-        (0, 0, corner_index),
-        (corner_index, corner_index, 0),
-        (corner_index, 0, corner_index),
-        (0, corner_index, corner_index),
-    ]
-}
-
-/// Check if the link end position is out of bounds of the cube
-fn link_out_of_bounds(vec: IVec3, bounds: i32) -> bool {
-    return vec.x < 0
-        || vec.x >= bounds
-        || vec.y < 0
-        || vec.y >= bounds
-        || vec.z < 0
-        || vec.z >= bounds;
+/// Build the lattice and start simulating it.
+/// Carries on from the checkpoint in the `CheckpointConfig` resource instead when one is set.
+pub fn create_simulation(
+    config: Res<LatticeConfig>,
+    checkpoint_config: Res<CheckpointConfig>,
+    thermostat_config: Res<ThermostatConfig>,
+    environment: Res<EnvironmentConfig>,
+    mut rng_source: ResMut<RandomSource>,
+    mut sim_data: ResMut<SimulationData>,
+    mut commands: Commands,
+) {
+    let thermostat = Thermostat::new(thermostat_config.model, thermostat_config.target.clone());
+    let mut sim = match &checkpoint_config.restart_from {
+        Some(path) => {
+            let checkpoint = match Checkpoint::load(path) {
+                Ok(checkpoint) => checkpoint,
+                Err(err) => {
+                    eprintln!("{err}");
+                    std::process::exit(1);
+                }
+            };
+            *rng_source = checkpoint.rng.random_source();
+            *sim_data = checkpoint.sim_data;
+            let sim = Simulation::restore(
+                checkpoint.simulation,
+                config.integrator,
+                config.damping,
+                thermostat,
+                config.threads,
+            );
+            println!(
+                "Restored checkpoint {} at step {}, {} s of simulated time. Random seed is {}",
+                path.display(),
+                sim.steps,
+                sim.time,
+                rng_source.seed
+            );
+            sim
+        }
+        None => {
+            let lattice = Lattice::generate(&config.lattice_params(), &mut rng_source.velocities);
+            Simulation::new(
+                lattice,
+                config.integrator,
+                config.damping,
+                thermostat,
+                config.threads,
+            )
+        }
+    };
+    sim.set_environment(environment.gravity, environment.ground(config.node_radius));
+    commands.insert_resource(LatticeSimulation(sim));
 }
 
-/// Spawn all nodes into the world
+/// Spawn an entity for every node
 pub fn create_all_nodes(
     mut lattice_gen: ResMut<LatticeGen>,
-    mut rng_source: ResMut<RandomSource>,
+    config: Res<LatticeConfig>,
+    sim: Res<LatticeSimulation>,
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+    // Not available when running headless, nodes are spawned without meshes then
+    assets: Option<Res<LatticeAssets>>,
 ) {
-    // Create a uniform distribution for random numbers
-    let rng = &mut rng_source.0;
-    // TODO: want to move the following as a resource function, but confused on the syntax,
-    // see https://doc.rust-lang.org/std/keyword.struct.html
-    let dist: Uniform<f32> =
-        Uniform::new_inclusive(lattice_config::START_VEL_MIN, lattice_config::START_VEL_MAX);
-
-    // Get indexes for which nodes should be static
-    let corners = get_static_node_indices();
-
-    // Define some variables for generating all the nodes
-    const NODES_DIM: u32 = lattice_config::DIM + 1;
-    let node_mesh = Sphere::new(lattice_config::NODE_RADIUS).mesh().uv(32, 18);
-
-    // Generate all nodes
-    for z in 0..NODES_DIM {
-        for y in 0..NODES_DIM {
-            for x in 0..NODES_DIM {
-                let starting_pos =
-                    Vec3::new(x as f32, y as f32, z as f32) * lattice_config::STARTING_LINK_LEN;
-                let starting_vel = Vec3::new(rng.sample(dist), rng.sample(dist), rng.sample(dist));
-
-                let node = Node {
-                    pos: starting_pos,
-                    vel: starting_vel,
-                    ..default()
-                };
-
-                let bundle = PbrBundle {
-                    mesh: meshes.add(node_mesh.clone()),
-                    material: materials.add(colors_config::NODE_COLOR),
-                    transform: Transform::from_translation(node.pos),
-                    ..default()
-                };
-
-                // Check if it's a corner node and anchor it by spawning it with the static component.
-                if corners.contains(&(x, y, z)) {
-                    lattice_gen.add(commands.spawn((bundle, node, Static)).id());
-                } else {
-                    lattice_gen.add(commands.spawn((bundle, node)).id());
-                }
-            }
+    for (idx, pos) in sim.lattice.nodes.pos.iter().enumerate() {
+        let mut entity = commands.spawn(Node(idx));
+        if let Some(assets) = assets.as_ref() {
+            entity.insert(PbrBundle {
+                mesh: assets.node_mesh.clone(),
+                material: assets.node_material.clone(),
+                transform: Transform::from_translation(*pos)
+                    .with_scale(Vec3::splat(config.node_radius)),
+                ..default()
+            });
         }
+        lattice_gen.data.push(entity.id());
     }
 
     println!("Number of lattice nodes is {}", lattice_gen.data.len());
-    debug_assert_eq!(
-        (calc_num_nodes(lattice_config::DIM)) as usize,
-        lattice_gen.data.len()
-    );
 }
 
+/// Spawn an entity for every link and angle link. Angle links have nothing to draw.
 pub fn generate_lattice(
+    config: Res<LatticeConfig>,
+    sim: Res<LatticeSimulation>,
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    lattice_gen: Res<LatticeGen>,
+    // Not available when running headless, links are spawned without meshes then
+    assets: Option<Res<LatticeAssets>>,
+    mut lattice_gen: ResMut<LatticeGen>,
 ) {
     println!("Generating Lattice");
 
-    // Turns out, you don't need all the directions cause you
-    // are only constructing the lattice in one direction.
-    // This gets rid of the duplication problem.
-    let dir_arr = [
-        IVec3::new(1, 0, 0),
-        IVec3::new(0, 1, 0),
-        IVec3::new(0, 0, 1),
-        IVec3::new(1, 1, 0),
-        IVec3::new(0, 1, 1),
-        IVec3::new(1, 0, 1),
-        IVec3::new(1, -1, 0),
-        IVec3::new(0, 1, -1),
-        IVec3::new(-1, 0, 1),
-    ];
-
-    let nodes_dim: i32 = lattice_config::DIM as i32 + 1;
-    let mut counter: u32 = 0;
-
-    // Fill out and spawn all links
-    for z in 0..nodes_dim {
-        for y in 0..nodes_dim {
-            for x in 0..nodes_dim {
-                for dir in dir_arr {
-                    // Get the two and from position of the nodes
-                    let curr_node_pos = IVec3 { x, y, z };
-                    let to_node_pos = curr_node_pos + dir;
-
-                    // Check if we are out of bounds
-                    if link_out_of_bounds(to_node_pos, nodes_dim) {
-                        continue;
-                    }
-
-                    // Get the entities for both nodes
-                    let to_node = lattice_gen.get(to_node_pos.as_uvec3());
-                    let from_node = lattice_gen.get(curr_node_pos.as_uvec3());
-
-                    // Determine the length of the spring, diagonal springs will not be the same starting length
-                    // as horizontal and vertical ones
-                    let length = (to_node_pos.as_vec3() - curr_node_pos.as_vec3()).length();
-
-                    // Generate a color that creates a gradient across the cube
-                    let position = curr_node_pos.as_vec3() / nodes_dim as f32;
-                    let color = Color::srgb(position.x, position.y, position.z);
-
-                    // Create a new Link / Spring and spawn
-                    let link = Link::new(lattice_config::SPRING_CONST, length, to_node, from_node);
-                    commands.spawn((
-                        PbrBundle {
-                            mesh: meshes.add(link.create_mesh()),
-                            material: materials.add(color),
-                            // transform will be corrected once springs positions update
-                            transform: Transform::from_translation(Vec3::ZERO),
-                            visibility: lattice_config::LINK_VISIBILITY,
-                            ..default()
-                        },
-                        link,
-                    ));
-
-                    counter += 1; // update counter and make sure we get all the links
-                }
-            }
-        }
-    }
-
-    let num_links = calc_num_links(lattice_config::DIM);
-    println!("number of springs generated is {counter} and expected was {num_links}",);
-    debug_assert_eq!(counter, num_links);
-}
-
-//-------------------------------------------------------
-// LatticeGen IMPL
-//-------------------------------------------------------
-
-impl LatticeGen {
-    /// Create a new lattice data structure for the given dimension
-    pub fn new(lattice_dimension: u32) -> Self {
-        Self {
-            // all internal math is based on num nodes on one side so dim + 1
-            nodes_dim: lattice_dimension + 1,
-            data: Vec::with_capacity(calc_num_nodes(lattice_dimension) as usize),
-        }
-    }
-
-    /// Get the data from the array given xyz index in the lattice.
-    pub fn get_data_idx(&self, x: u32, y: u32, z: u32) -> usize {
-        (z * self.nodes_dim * self.nodes_dim + y * self.nodes_dim + x) as usize
-    }
-
-    /// Get the entity given the xyz index in the lattice.
-    pub fn get(&self, UVec3 { x, y, z }: UVec3) -> Entity {
-        debug_assert!(x <= (self.nodes_dim + 1));
-        debug_assert!(y <= (self.nodes_dim + 1));
-        debug_assert!(z <= (self.nodes_dim + 1));
-        // println!("accessing {} {} {}", x,y,z);
-        let idx = self.get_data_idx(x, y, z);
-        self.data[idx]
+    let lattice = &sim.lattice;
+    let far_corner = lattice.far_corner();
+
+    for (from, to, link) in lattice.links.iter() {
+        let from_pos = lattice.nodes.pos[from];
+
+        // Generate a color that creates a gradient across the cube
+        let color = from_pos / far_corner;
+        let scale = Vec3::new(config.link_radius, config.link_radius, link.orig_length);
+
+        let link = Link { to, from };
+        let entity = match assets.as_ref() {
+            Some(assets) => commands.spawn((
+                PbrBundle {
+                    mesh: assets.link_mesh.clone(),
+                    material: assets.link_material(color),
+                    // transform will be corrected once springs positions update
+                    transform: Transform::from_scale(scale),
+                    visibility: config.link_visibility(),
+                    ..default()
+                },
+                link,
+            )),
+            None => commands.spawn(link),
+        };
+        lattice_gen.links.insert((from, to), entity.id());
     }
 
-    /// Add a node to the lattice.
-    pub fn add(&mut self, node: Entity) {
-        self.data.push(node);
-    }
-}
+    let num_links =
+        lattice_core::calc_num_links(lattice.dims, lattice.kind, config.bond_shells.len());
+    println!(
+        "number of springs generated is {} and expected was {num_links}",
+        lattice_gen.links.len()
+    );
 
-/// Get number of nodes in a lattice
-pub fn calc_num_nodes(lattice_dim: u32) -> u32 {
-    debug_assert!(lattice_dim > 0);
-    match u32::checked_pow(lattice_dim + 1, 3) {
-        None => panic!("overflow while calculating number of lattice nodes"),
-        Some(val) => val,
+    for angle in lattice.angles.iter() {
+        commands.spawn(AngleLink {
+            vertex: angle.vertex,
+            ends: angle.ends,
+        });
     }
-}
-
-/// Get number of links in a lattice given dim.
-/// Dim is the number of "1x1x1 cubes" on one side of the cube lattice.
-pub fn calc_num_links(lattice_dim: u32) -> u32 {
-    debug_assert!(lattice_dim > 0);
-    match u32::checked_mul(3 * lattice_dim * (lattice_dim + 1), 3 * lattice_dim + 1) {
-        None => panic!("overflow while calculating number of lattice links"),
-        Some(val) => val,
+    if !lattice.angles.is_empty() {
+        println!(
+            "number of angle links generated is {}",
+            lattice.angles.len()
+        );
     }
 }
 
@@ -254,19 +193,38 @@ pub fn calc_num_links(lattice_dim: u32) -> u32 {
 // RandomSourcePlugin IMPL
 //-------------------------------------------------------
 
-impl Plugin for RandomSourcePlugin {
-    fn build(&self, app: &mut App) {
-        app.add_systems(Startup, Self::add_rng);
+impl RandomSource {
+    // Stream of the seed each use draws from, never change these or old seeds give different runs
+    const VELOCITY_STREAM: u64 = 0;
+    const THERMOSTAT_STREAM: u64 = 1;
+    const DEFECT_STREAM: u64 = 2;
+
+    /// Start every stream from the seed
+    pub fn new(seed: u64) -> Self {
+        // https://rust-random.github.io/book/guide-rngs.html
+        let stream = |stream| {
+            let mut rng = ChaCha8Rng::seed_from_u64(seed);
+            rng.set_stream(stream);
+            rng
+        };
+        RandomSource {
+            seed,
+            velocities: stream(Self::VELOCITY_STREAM),
+            thermostat: stream(Self::THERMOSTAT_STREAM),
+            defects: stream(Self::DEFECT_STREAM),
+        }
     }
 }
 
-impl RandomSourcePlugin {
-    fn add_rng(mut commands: Commands) {
-        // https://rust-random.github.io/book/guide-rngs.html
-        // TODO: currently deterministic but change to get varying results.
-        let keyboard_mashing: u64 = 459347051375372;
-        let seeded_rng = ChaCha8Rng::seed_from_u64(keyboard_mashing);
-        commands.insert_resource(RandomSource(seeded_rng));
-        println!("Successfully added RNG");
+impl Plugin for RandomSourcePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RandomConfig>();
+        let seed = match app.world().resource::<RandomConfig>().seed {
+            Some(seed) => seed,
+            None => OsRng.next_u64(),
+        };
+        // Printed so any run can be repeated, even one with a seed from the OS
+        println!("Random seed is {seed}");
+        app.insert_resource(RandomSource::new(seed));
     }
 }
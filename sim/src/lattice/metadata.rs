@@ -0,0 +1,87 @@
+use bevy::prelude::*;
+use serde::Serialize;
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::config::{CheckpointConfig, LatticeConfig};
+use crate::lattice::lattice_gen::RandomSource;
+use crate::lattice::LatticeSimulation;
+
+//-------------------------------------------------------
+// STRUCTS
+//-------------------------------------------------------
+
+/// Every directory output is written to. Each one gets a metadata.json
+/// describing the run once the lattice is generated.
+#[derive(Resource, Default)]
+pub struct OutputDirs(Vec<PathBuf>);
+
+/// What is needed to repeat a run
+#[derive(Serialize)]
+struct RunMetadata<'a> {
+    seed: u64,
+    time_step: f32,
+    /// Checkpoint the run carried on from, if any
+    restarted_from: Option<&'a Path>,
+    /// Step the run started at, 0 unless it carried on from a checkpoint
+    start_step: u64,
+}
+
+//-------------------------------------------------------
+// IMPLEMENTATIONS
+//-------------------------------------------------------
+
+impl OutputDirs {
+    /// Name of the file written to every directory
+    const FILE_NAME: &'static str = "metadata.json";
+
+    /// Add a directory, call while building a plugin that writes output
+    pub fn register(app: &mut App, dir: &Path) {
+        let mut dirs = app
+            .world_mut()
+            .get_resource_or_insert_with(OutputDirs::default);
+        if !dirs.0.iter().any(|existing| existing == dir) {
+            dirs.0.push(dir.to_path_buf());
+        }
+    }
+}
+
+/// Write the metadata to a directory
+fn write_metadata(dir: &Path, metadata: &RunMetadata) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(dir.join(OutputDirs::FILE_NAME))?);
+    serde_json::to_writer_pretty(&mut out, metadata)?;
+    writeln!(out)?;
+    out.flush()
+}
+
+//-------------------------------------------------------
+// SYSTEMS
+//-------------------------------------------------------
+
+/// Write metadata.json to every output directory. Must run after the simulation is created
+/// so a restored run records the seed of its checkpoint.
+pub fn write_run_metadata(
+    dirs: Option<Res<OutputDirs>>,
+    rng: Res<RandomSource>,
+    sim: Res<LatticeSimulation>,
+    config: Res<LatticeConfig>,
+    checkpoint_config: Res<CheckpointConfig>,
+) {
+    let Some(dirs) = dirs else {
+        return;
+    };
+    let metadata = RunMetadata {
+        seed: rng.seed,
+        time_step: config.time_step,
+        restarted_from: checkpoint_config.restart_from.as_deref(),
+        start_step: sim.steps,
+    };
+    for dir in dirs.0.iter() {
+        let path = dir.join(OutputDirs::FILE_NAME);
+        write_metadata(dir, &metadata)
+            .unwrap_or_else(|err| panic!("could not write {}: {err}", path.display()));
+    }
+}
@@ -0,0 +1,260 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+};
+
+use crate::config::TelemetryConfig;
+use crate::lattice::fracture::break_links;
+use crate::lattice::metadata::OutputDirs;
+use crate::lattice::{LatticeSimulation, Simulation};
+
+//-------------------------------------------------------
+// STRUCTS
+//-------------------------------------------------------
+
+/// Quantities the telemetry can record
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Observable {
+    /// J
+    KineticEnergy,
+    /// J
+    PotentialEnergy,
+    /// J
+    TotalEnergy,
+    /// Work removed by damping since the start, J
+    DissipatedWork,
+    /// Kinetic temperature in energy units, J
+    Temperature,
+    /// Target temperature of the thermostat, J
+    TargetTemperature,
+    /// Minus the mean normal virial stress, Pa
+    Pressure,
+    /// Virial stress tensor as xx, yy, zz, yz, xz, xy, Pa. Positive is tension.
+    Stress,
+    /// Mass weighted average node position, m
+    CenterOfMass,
+    /// Total linear momentum, kg m/s
+    Momentum,
+    /// Largest strain of any intact link, empty once every link is broken
+    MaxStrain,
+}
+
+/// File formats the telemetry can be written in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TelemetryFormat {
+    /// telemetry.csv with a header row, vectors get a column per component
+    Csv,
+    /// telemetry.jsonl with one object per row, vectors are arrays
+    JsonLines,
+}
+
+/// Records the observables set in the `TelemetryConfig` resource against the fixed step
+/// and simulated time every given number of fixed steps. Does nothing when the interval is 0.
+pub struct TelemetryPlugin;
+
+/// Open telemetry file and what goes in it
+#[derive(Resource)]
+struct TelemetryWriter {
+    /// Fixed steps between rows
+    every: u64,
+    format: TelemetryFormat,
+    observables: Vec<Observable>,
+    file: BufWriter<File>,
+}
+
+/// Value of an observable at one step
+enum Measurement {
+    Scalar(Option<f32>),
+    Vector(Vec3),
+    /// Independent components of a symmetric tensor
+    Components(Vec<f32>),
+}
+
+//-------------------------------------------------------
+// IMPLEMENTATIONS
+//-------------------------------------------------------
+
+impl Observable {
+    /// Name of the observable in the output
+    fn name(self) -> &'static str {
+        match self {
+            Observable::KineticEnergy => "kinetic_energy",
+            Observable::PotentialEnergy => "potential_energy",
+            Observable::TotalEnergy => "total_energy",
+            Observable::DissipatedWork => "dissipated_work",
+            Observable::Temperature => "temperature",
+            Observable::TargetTemperature => "target_temperature",
+            Observable::Pressure => "pressure",
+            Observable::Stress => "stress",
+            Observable::CenterOfMass => "center_of_mass",
+            Observable::Momentum => "momentum",
+            Observable::MaxStrain => "max_strain",
+        }
+    }
+
+    /// Get the value of the observable after the last step
+    fn measure(self, sim: &Simulation) -> Measurement {
+        match self {
+            Observable::KineticEnergy => Measurement::Scalar(Some(sim.energy.kinetic)),
+            Observable::PotentialEnergy => Measurement::Scalar(Some(sim.energy.potential)),
+            Observable::TotalEnergy => Measurement::Scalar(Some(sim.energy.total)),
+            Observable::DissipatedWork => Measurement::Scalar(Some(sim.energy.dissipated_work)),
+            Observable::Temperature => Measurement::Scalar(Some(sim.temperature())),
+            Observable::TargetTemperature => {
+                Measurement::Scalar(Some(sim.thermostat.target.at(sim.time)))
+            }
+            Observable::Pressure => Measurement::Scalar(Some(sim.pressure())),
+            Observable::Stress => {
+                let stress = sim.stress();
+                Measurement::Components(vec![
+                    stress.x_axis.x,
+                    stress.y_axis.y,
+                    stress.z_axis.z,
+                    stress.z_axis.y,
+                    stress.z_axis.x,
+                    stress.y_axis.x,
+                ])
+            }
+            Observable::CenterOfMass => Measurement::Vector(sim.center_of_mass()),
+            Observable::Momentum => Measurement::Vector(sim.momentum()),
+            Observable::MaxStrain => Measurement::Scalar(sim.max_strain()),
+        }
+    }
+
+    /// Column names of the observable in a csv header
+    fn csv_columns(self) -> Vec<String> {
+        match self {
+            Observable::CenterOfMass | Observable::Momentum => ["x", "y", "z"]
+                .iter()
+                .map(|axis| format!("{}_{axis}", self.name()))
+                .collect(),
+            Observable::Stress => ["xx", "yy", "zz", "yz", "xz", "xy"]
+                .iter()
+                .map(|axis| format!("{}_{axis}", self.name()))
+                .collect(),
+            _ => vec![self.name().to_string()],
+        }
+    }
+}
+
+impl TelemetryFormat {
+    /// Name of the file the format is written to
+    fn file_name(self) -> &'static str {
+        match self {
+            TelemetryFormat::Csv => "telemetry.csv",
+            TelemetryFormat::JsonLines => "telemetry.jsonl",
+        }
+    }
+}
+
+impl TelemetryWriter {
+    /// Write the csv header, json lines don't have one
+    fn write_header(&mut self) -> io::Result<()> {
+        if self.format != TelemetryFormat::Csv {
+            return Ok(());
+        }
+        let mut columns = vec!["step".to_string(), "time".to_string()];
+        columns.extend(self.observables.iter().flat_map(|obs| obs.csv_columns()));
+        writeln!(self.file, "{}", columns.join(","))
+    }
+
+    /// Write one row with every observable
+    fn write_row(&mut self, sim: &Simulation) -> io::Result<()> {
+        let (step, time) = (sim.steps, sim.time);
+        match self.format {
+            TelemetryFormat::Csv => {
+                write!(self.file, "{step},{time}")?;
+                for obs in self.observables.iter() {
+                    match obs.measure(sim) {
+                        Measurement::Scalar(Some(value)) => write!(self.file, ",{value}")?,
+                        Measurement::Scalar(None) => write!(self.file, ",")?,
+                        Measurement::Vector(value) => {
+                            write!(self.file, ",{},{},{}", value.x, value.y, value.z)?
+                        }
+                        Measurement::Components(values) => {
+                            for value in values {
+                                write!(self.file, ",{value}")?;
+                            }
+                        }
+                    }
+                }
+            }
+            TelemetryFormat::JsonLines => {
+                write!(self.file, r#"{{"step":{step},"time":{time}"#)?;
+                for obs in self.observables.iter() {
+                    // serde_json writes anything that isn't finite as null
+                    let value = match obs.measure(sim) {
+                        Measurement::Scalar(value) => serde_json::to_string(&value),
+                        Measurement::Vector(value) => serde_json::to_string(&value.to_array()),
+                        Measurement::Components(values) => serde_json::to_string(&values),
+                    }?;
+                    write!(self.file, r#","{}":{value}"#, obs.name())?;
+                }
+                write!(self.file, "}}")?;
+            }
+        }
+        writeln!(self.file)
+    }
+}
+
+impl Plugin for TelemetryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TelemetryConfig>();
+        let config = app.world().resource::<TelemetryConfig>().clone();
+        if config.every == 0 {
+            return;
+        }
+
+        fs::create_dir_all(&config.output_dir).unwrap_or_else(|err| {
+            panic!(
+                "could not create output directory {}: {err}",
+                config.output_dir.display()
+            )
+        });
+        OutputDirs::register(app, &config.output_dir);
+        let path = config.output_dir.join(config.format.file_name());
+        let file = File::create(&path)
+            .unwrap_or_else(|err| panic!("could not create {}: {err}", path.display()));
+        println!("Writing telemetry to {}", path.display());
+
+        let mut writer = TelemetryWriter {
+            every: config.every,
+            format: config.format,
+            observables: config.observables,
+            file: BufWriter::new(file),
+        };
+        writer
+            .write_header()
+            .unwrap_or_else(|err| panic!("could not write {}: {err}", path.display()));
+
+        app.insert_resource(writer);
+        app.add_systems(
+            FixedUpdate,
+            write_telemetry
+                .after(break_links)
+                .run_if(resource_exists::<LatticeSimulation>),
+        );
+    }
+}
+
+//-------------------------------------------------------
+// SYSTEMS
+//-------------------------------------------------------
+
+/// Write a row of observables when the step is on the interval
+fn write_telemetry(sim: Res<LatticeSimulation>, mut writer: ResMut<TelemetryWriter>) {
+    if !sim.steps.is_multiple_of(writer.every) {
+        return;
+    }
+
+    // Flushed every row so the file can be followed while the run goes
+    let file_name = writer.format.file_name();
+    writer
+        .write_row(&sim)
+        .and_then(|_| writer.file.flush())
+        .unwrap_or_else(|err| panic!("could not write {file_name}: {err}"));
+}
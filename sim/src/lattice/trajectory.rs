@@ -0,0 +1,201 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+};
+
+use crate::config::TrajectoryConfig;
+use crate::lattice::fracture::break_links;
+use crate::lattice::metadata::OutputDirs;
+use crate::lattice::{LatticeSimulation, Simulation};
+
+//-------------------------------------------------------
+// STRUCTS
+//-------------------------------------------------------
+
+/// File formats the trajectory can be written in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrajectoryFormat {
+    /// Extended XYZ, trajectory.xyz. Read by OVITO and ASE.
+    ExtendedXyz,
+    /// LAMMPS custom dump, trajectory.lammpstrj. Read by OVITO and most MD tools.
+    LammpsDump,
+}
+
+/// Appends the state of every node to the files set in the `TrajectoryConfig` resource
+/// every given number of fixed steps. Does nothing when the interval is 0.
+pub struct TrajectoryPlugin;
+
+/// Open trajectory files
+#[derive(Resource)]
+struct TrajectoryWriter {
+    /// Fixed steps between frames
+    every: u64,
+    files: Vec<(TrajectoryFormat, BufWriter<File>)>,
+}
+
+//-------------------------------------------------------
+// IMPLEMENTATIONS
+//-------------------------------------------------------
+
+impl TrajectoryFormat {
+    /// Name of the file the format is written to
+    fn file_name(self) -> &'static str {
+        match self {
+            TrajectoryFormat::ExtendedXyz => "trajectory.xyz",
+            TrajectoryFormat::LammpsDump => "trajectory.lammpstrj",
+        }
+    }
+
+    /// Append one frame with the state of every node
+    fn write_frame(
+        self,
+        out: &mut impl Write,
+        sim: &Simulation,
+        step: u64,
+        time: f64,
+    ) -> io::Result<()> {
+        match self {
+            TrajectoryFormat::ExtendedXyz => write_xyz_frame(out, sim, step, time),
+            TrajectoryFormat::LammpsDump => write_lammps_frame(out, sim, step, time),
+        }
+    }
+}
+
+impl Plugin for TrajectoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TrajectoryConfig>();
+        let config = app.world().resource::<TrajectoryConfig>().clone();
+        if config.every == 0 {
+            return;
+        }
+
+        fs::create_dir_all(&config.output_dir).unwrap_or_else(|err| {
+            panic!(
+                "could not create output directory {}: {err}",
+                config.output_dir.display()
+            )
+        });
+        OutputDirs::register(app, &config.output_dir);
+        let files = config
+            .formats
+            .iter()
+            .map(|format| {
+                let path = config.output_dir.join(format.file_name());
+                let file = File::create(&path)
+                    .unwrap_or_else(|err| panic!("could not create {}: {err}", path.display()));
+                println!("Writing trajectory to {}", path.display());
+                (*format, BufWriter::new(file))
+            })
+            .collect();
+
+        app.insert_resource(TrajectoryWriter {
+            every: config.every,
+            files,
+        });
+        app.add_systems(
+            FixedUpdate,
+            write_trajectory
+                .after(break_links)
+                .run_if(resource_exists::<LatticeSimulation>),
+        );
+    }
+}
+
+//-------------------------------------------------------
+// SYSTEMS
+//-------------------------------------------------------
+
+/// Write a frame to every trajectory file when the step is on the interval.
+/// Forces are the ones from the last force evaluation of the step.
+fn write_trajectory(sim: Res<LatticeSimulation>, mut writer: ResMut<TrajectoryWriter>) {
+    let (step, time) = (sim.steps, sim.time);
+    if !step.is_multiple_of(writer.every) {
+        return;
+    }
+
+    for (format, file) in writer.files.iter_mut() {
+        // Flushed every frame so a crashed run still leaves every finished frame
+        format
+            .write_frame(file, &sim, step, time)
+            .and_then(|_| file.flush())
+            .unwrap_or_else(|err| panic!("could not write {}: {err}", format.file_name()));
+    }
+}
+
+/// Columns of an extended XYZ frame as name:type:count
+const XYZ_PROPERTIES: &str = "species:S:1:pos:R:3:velo:R:3:forces:R:3:static:I:1";
+
+/// One extended XYZ frame, the node count, a comment line describing the columns,
+/// then a line per node. Nodes have the placeholder species X.
+fn write_xyz_frame(out: &mut impl Write, sim: &Simulation, step: u64, time: f64) -> io::Result<()> {
+    let nodes = &sim.lattice.nodes;
+    writeln!(out, "{}", nodes.len())?;
+    writeln!(
+        out,
+        "Properties={XYZ_PROPERTIES} Time={time} Step={step} pbc=\"F F F\""
+    )?;
+    for idx in 0..nodes.len() {
+        let (pos, vel, force) = (nodes.pos[idx], nodes.vel[idx], nodes.sum_forces[idx]);
+        writeln!(
+            out,
+            "X {} {} {} {} {} {} {} {} {} {}",
+            pos.x,
+            pos.y,
+            pos.z,
+            vel.x,
+            vel.y,
+            vel.z,
+            force.x,
+            force.y,
+            force.z,
+            nodes.is_static(idx) as u8
+        )?;
+    }
+    Ok(())
+}
+
+/// One LAMMPS custom dump frame. The box is the bounding box of the nodes and isn't periodic.
+/// Ids start at 1 and are the node index plus one.
+fn write_lammps_frame(
+    out: &mut impl Write,
+    sim: &Simulation,
+    step: u64,
+    time: f64,
+) -> io::Result<()> {
+    let nodes = &sim.lattice.nodes;
+    let (min, max) = nodes.pos.iter().fold(
+        (Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY)),
+        |(min, max), pos| (min.min(*pos), max.max(*pos)),
+    );
+
+    writeln!(out, "ITEM: TIME\n{time}")?;
+    writeln!(out, "ITEM: TIMESTEP\n{step}")?;
+    writeln!(out, "ITEM: NUMBER OF ATOMS\n{}", nodes.len())?;
+    writeln!(out, "ITEM: BOX BOUNDS ff ff ff")?;
+    for axis in 0..3 {
+        writeln!(out, "{} {}", min[axis], max[axis])?;
+    }
+    writeln!(out, "ITEM: ATOMS id type x y z vx vy vz fx fy fz static")?;
+    for idx in 0..nodes.len() {
+        let (pos, vel, force) = (nodes.pos[idx], nodes.vel[idx], nodes.sum_forces[idx]);
+        writeln!(
+            out,
+            "{} 1 {} {} {} {} {} {} {} {} {} {}",
+            idx + 1,
+            pos.x,
+            pos.y,
+            pos.z,
+            vel.x,
+            vel.y,
+            vel.z,
+            force.x,
+            force.y,
+            force.z,
+            nodes.is_static(idx) as u8
+        )?;
+    }
+    Ok(())
+}
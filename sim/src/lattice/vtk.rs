@@ -0,0 +1,236 @@
+use bevy::prelude::*;
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use crate::config::VtkConfig;
+use crate::lattice::fracture::break_links;
+use crate::lattice::metadata::OutputDirs;
+use crate::lattice::{LatticeSimulation, Simulation};
+
+//-------------------------------------------------------
+// STRUCTS
+//-------------------------------------------------------
+
+/// Writes the lattice as a VTK unstructured grid every given number of fixed steps,
+/// nodes as points and links as line cells. Each frame is its own .vtu file and
+/// lattice.pvd collects them into a time series for ParaView.
+/// Does nothing when the interval in the `VtkConfig` resource is 0.
+pub struct VtkPlugin;
+
+/// Frames written so far
+#[derive(Resource)]
+struct VtkWriter {
+    /// Fixed steps between frames
+    every: u64,
+    /// Simulated time and file name of every frame written
+    frames: Vec<(f64, String)>,
+}
+
+//-------------------------------------------------------
+// IMPLEMENTATIONS
+//-------------------------------------------------------
+
+impl Plugin for VtkPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<VtkConfig>();
+        let config = app.world().resource::<VtkConfig>().clone();
+        if config.every == 0 {
+            return;
+        }
+
+        fs::create_dir_all(&config.output_dir).unwrap_or_else(|err| {
+            panic!(
+                "could not create output directory {}: {err}",
+                config.output_dir.display()
+            )
+        });
+        OutputDirs::register(app, &config.output_dir);
+        println!(
+            "Writing VTK time series to {}",
+            config.output_dir.join(VtkWriter::COLLECTION).display()
+        );
+
+        app.insert_resource(VtkWriter {
+            every: config.every,
+            frames: Vec::new(),
+        });
+        app.add_systems(
+            FixedUpdate,
+            write_vtk
+                .after(break_links)
+                .run_if(resource_exists::<LatticeSimulation>),
+        );
+    }
+}
+
+impl VtkWriter {
+    /// File listing every frame with its time
+    const COLLECTION: &'static str = "lattice.pvd";
+
+    /// Rewrite the collection so it is complete even if the run stops early
+    fn write_collection(&self, output_dir: &Path) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(output_dir.join(Self::COLLECTION))?);
+        writeln!(out, r#"<?xml version="1.0"?>"#)?;
+        writeln!(
+            out,
+            r#"<VTKFile type="Collection" version="0.1" byte_order="LittleEndian">"#
+        )?;
+        writeln!(out, "  <Collection>")?;
+        for (time, file_name) in self.frames.iter() {
+            writeln!(
+                out,
+                r#"    <DataSet timestep="{time}" group="" part="0" file="{file_name}"/>"#
+            )?;
+        }
+        writeln!(out, "  </Collection>")?;
+        writeln!(out, "</VTKFile>")?;
+        out.flush()
+    }
+}
+
+//-------------------------------------------------------
+// SYSTEMS
+//-------------------------------------------------------
+
+/// Write a frame and update the collection when the step is on the interval
+fn write_vtk(sim: Res<LatticeSimulation>, config: Res<VtkConfig>, mut writer: ResMut<VtkWriter>) {
+    let step = sim.steps;
+    if !step.is_multiple_of(writer.every) {
+        return;
+    }
+
+    let file_name = format!("lattice_{step:06}.vtu");
+    let path = config.output_dir.join(&file_name);
+    writer.frames.push((sim.time, file_name));
+
+    File::create(&path)
+        .and_then(|file| write_frame(&mut BufWriter::new(file), &sim))
+        .unwrap_or_else(|err| panic!("could not write {}: {err}", path.display()));
+    writer
+        .write_collection(&config.output_dir)
+        .unwrap_or_else(|err| panic!("could not write {}: {err}", VtkWriter::COLLECTION));
+}
+
+/// Write the lattice as an ascii VTK unstructured grid.
+/// Points carry velocity, force and kinetic energy. Cells carry strain and the axial
+/// spring force, both positive in tension.
+fn write_frame(out: &mut impl Write, sim: &Simulation) -> io::Result<()> {
+    let nodes = &sim.lattice.nodes;
+    let links = &sim.lattice.links;
+
+    writeln!(out, r#"<?xml version="1.0"?>"#)?;
+    writeln!(
+        out,
+        r#"<VTKFile type="UnstructuredGrid" version="1.0" byte_order="LittleEndian">"#
+    )?;
+    writeln!(out, "  <UnstructuredGrid>")?;
+    writeln!(
+        out,
+        r#"    <Piece NumberOfPoints="{}" NumberOfCells="{}">"#,
+        nodes.len(),
+        links.len()
+    )?;
+
+    writeln!(
+        out,
+        r#"      <PointData Vectors="velocity" Scalars="kinetic_energy">"#
+    )?;
+    write_vectors(out, "velocity", &nodes.vel)?;
+    write_vectors(out, "force", &nodes.sum_forces)?;
+    write_array(
+        out,
+        "Float32",
+        "kinetic_energy",
+        (0..nodes.len()).map(|idx| 0.5 * nodes.mass[idx] * nodes.vel[idx].length_squared()),
+    )?;
+    writeln!(out, "      </PointData>")?;
+
+    let lengths: Vec<f32> = links
+        .iter()
+        .map(|(from, to, _)| nodes.pos[from].distance(nodes.pos[to]))
+        .collect();
+    writeln!(out, r#"      <CellData Scalars="strain">"#)?;
+    write_array(
+        out,
+        "Float32",
+        "strain",
+        links
+            .iter()
+            .zip(lengths.iter())
+            .map(|((_, _, link), length)| link.strain(*length)),
+    )?;
+    write_array(
+        out,
+        "Float32",
+        "force",
+        links
+            .iter()
+            .zip(lengths.iter())
+            .map(|((_, _, link), length)| -link.force(*length)),
+    )?;
+    writeln!(out, "      </CellData>")?;
+
+    writeln!(out, "      <Points>")?;
+    write_vectors(out, "position", &nodes.pos)?;
+    writeln!(out, "      </Points>")?;
+
+    // Every cell is a line between the two nodes of a link
+    writeln!(out, "      <Cells>")?;
+    write_array(
+        out,
+        "Int64",
+        "connectivity",
+        links.iter().flat_map(|(from, to, _)| [from, to]),
+    )?;
+    write_array(
+        out,
+        "Int64",
+        "offsets",
+        (1..=links.len()).map(|cell| 2 * cell),
+    )?;
+    const VTK_LINE: u8 = 3;
+    write_array(
+        out,
+        "UInt8",
+        "types",
+        std::iter::repeat_n(VTK_LINE, links.len()),
+    )?;
+    writeln!(out, "      </Cells>")?;
+
+    writeln!(out, "    </Piece>")?;
+    writeln!(out, "  </UnstructuredGrid>")?;
+    writeln!(out, "</VTKFile>")?;
+    out.flush()
+}
+
+/// Write a data array with one value per line
+fn write_array<T: std::fmt::Display>(
+    out: &mut impl Write,
+    data_type: &str,
+    name: &str,
+    values: impl Iterator<Item = T>,
+) -> io::Result<()> {
+    writeln!(
+        out,
+        r#"        <DataArray type="{data_type}" Name="{name}" format="ascii">"#
+    )?;
+    for value in values {
+        writeln!(out, "          {value}")?;
+    }
+    writeln!(out, "        </DataArray>")
+}
+
+/// Write a data array of three component vectors with one vector per line
+fn write_vectors(out: &mut impl Write, name: &str, values: &[Vec3]) -> io::Result<()> {
+    writeln!(
+        out,
+        r#"        <DataArray type="Float32" Name="{name}" NumberOfComponents="3" format="ascii">"#
+    )?;
+    for value in values {
+        writeln!(out, "          {} {} {}", value.x, value.y, value.z)?;
+    }
+    writeln!(out, "        </DataArray>")
+}
@@ -1,13 +1,7 @@
-use bevy::{
-    diagnostic::{
-        DiagnosticsStore, EntityCountDiagnosticsPlugin, FrameTimeDiagnosticsPlugin,
-        LogDiagnosticsPlugin,
-    },
-    input::common_conditions::input_just_pressed,
-    prelude::*,
-    time::common_conditions::{on_timer, once_after_delay, repeating_after_delay},
-};
+use bevy::{input::common_conditions::input_just_pressed, prelude::*};
+use config::SimConfig;
 use lattice::LatticePlugin;
+use std::path::PathBuf;
 
 // https://docs.rs/smooth-bevy-cameras/0.11.0/smooth_bevy_cameras/
 // https://github.com/bonsairobo/smooth-bevy-cameras/blob/main/examples/simple_unreal.rs
@@ -20,31 +14,104 @@ mod config;
 mod lattice;
 mod scene;
 
+//-------------------------------------------------------
+// STRUCTS
+//-------------------------------------------------------
+
+/// Command line arguments
+#[derive(Default)]
+struct CliArgs {
+    /// Config file given with --config <path>
+    config: Option<PathBuf>,
+    /// Run without a window with --headless
+    headless: bool,
+    /// Seed given with --seed <seed>, overrides the config
+    seed: Option<u64>,
+}
+
 fn main() {
-    App::new()
-        .add_plugins((
-            DefaultPlugins,
-            LookTransformPlugin,
-            UnrealCameraPlugin::default(),
-            LatticePlugin,
-            // FrameTimeDiagnosticsPlugin,
-            // EntityCountDiagnosticsPlugin::default(),
-            // LogDiagnosticsPlugin::default(),
-            //TODO: check out https://docs.rs/bevy/0.14.2/bevy/render/diagnostic/struct.RenderDiagnosticsPlugin.html
-        ))
-        .insert_resource(ClearColor(Srgba::hex("3b4a56").unwrap().into()))
-        .add_systems(Startup, scene::setup)
-        // no stopping user from running draw_xyz
-        .add_systems(
-            Update,
-            scene::draw_xyz.run_if(input_just_pressed(KeyCode::KeyO)),
-        )
-        .add_systems(
-            Update,
-            scene::lock_camera.run_if(input_just_pressed(KeyCode::KeyL)),
-        )
-        // .add_systems(Update, scene::animate_ground)
-        .run();
+    let args = CliArgs::parse();
+
+    // Optional config file, anything it leaves out uses the defaults in config.rs
+    let mut config = match args.config {
+        Some(path) => match SimConfig::load(&path) {
+            Ok(config) => {
+                println!("Loaded config from {}", path.display());
+                config
+            }
+            Err(err) => {
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+        },
+        None => SimConfig::default(),
+    };
+    if let Some(seed) = args.seed {
+        config.random.seed = Some(seed);
+    }
+
+    let mut app = App::new();
+    // Config resources have to exist before the plugins that read them are built
+    config.insert_resources(&mut app);
+
+    if args.headless {
+        app.add_plugins((MinimalPlugins, LatticePlugin { headless: true }))
+            .run();
+        return;
+    }
+
+    app.add_plugins((
+        DefaultPlugins,
+        LookTransformPlugin,
+        UnrealCameraPlugin::default(),
+        LatticePlugin::default(),
+        // FrameTimeDiagnosticsPlugin,
+        // EntityCountDiagnosticsPlugin::default(),
+        // LogDiagnosticsPlugin::default(),
+        //TODO: check out https://docs.rs/bevy/0.14.2/bevy/render/diagnostic/struct.RenderDiagnosticsPlugin.html
+    ))
+    .insert_resource(ClearColor(Srgba::hex("3b4a56").unwrap().into()))
+    .add_systems(Startup, (scene::setup, scene::setup_ground))
+    // no stopping user from running draw_xyz
+    .add_systems(
+        Update,
+        scene::draw_xyz.run_if(input_just_pressed(KeyCode::KeyO)),
+    )
+    .add_systems(
+        Update,
+        scene::lock_camera.run_if(input_just_pressed(KeyCode::KeyL)),
+    )
+    .run();
+}
+
+impl CliArgs {
+    /// Read the arguments the app was started with, exits on anything it doesn't know
+    fn parse() -> Self {
+        let mut cli_args = CliArgs::default();
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--config" => match args.next() {
+                    Some(path) => cli_args.config = Some(PathBuf::from(path)),
+                    None => exit_with_usage("--config needs a path"),
+                },
+                "--headless" => cli_args.headless = true,
+                "--seed" => match args.next().map(|seed| seed.parse()) {
+                    Some(Ok(seed)) => cli_args.seed = Some(seed),
+                    _ => exit_with_usage("--seed needs a whole number from 0 to 2^64 - 1"),
+                },
+                _ => exit_with_usage(&format!("unknown argument {arg}")),
+            }
+        }
+        cli_args
+    }
+}
+
+/// Print the problem with the arguments and how to use them, then exit
+fn exit_with_usage(msg: &str) -> ! {
+    eprintln!("{msg}");
+    eprintln!("usage: sim [--config <path>] [--headless] [--seed <seed>]");
+    std::process::exit(2);
 }
 
 // driver code
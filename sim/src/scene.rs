@@ -1,11 +1,13 @@
-use crate::config::{axis_config, cam_config, colors_config, lattice_config, lights_config};
-use bevy::{math::VectorSpace, prelude::*};
+use crate::config::{
+    axis_config, CamConfig, ColorsConfig, EnvironmentConfig, LatticeConfig, LightsConfig,
+};
+use bevy::prelude::*;
 use smooth_bevy_cameras::controllers::unreal::{UnrealCameraBundle, UnrealCameraController};
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
 
 //////////////////////////////////////////////////
-/// SCENE, ENVIRONMENT, SETUP
+// SCENE, ENVIRONMENT, SETUP
 //////////////////////////////////////////////////
 
 #[derive(EnumIter)]
@@ -18,19 +20,36 @@ enum Axis {
 /// Setup scene / environment
 pub fn setup(
     mut commands: Commands,
-    mut config_store: ResMut<GizmoConfigStore>,
+    lattice: Res<LatticeConfig>,
+    lights: Res<LightsConfig>,
+    cam: Res<CamConfig>,
+) {
+    create_light(&mut commands, &lattice, &lights);
+    create_cameras(&mut commands, &lattice, &cam);
+}
+
+/// Show the ground when the physics has one
+pub fn setup_ground(
+    mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    environment: Res<EnvironmentConfig>,
 ) {
-    create_light(&mut commands, &mut config_store);
-    create_cameras(&mut commands);
-    // create_ground(&mut commands,&mut meshes, &mut materials);
+    if environment.ground {
+        create_ground(
+            &mut commands,
+            &mut meshes,
+            &mut materials,
+            environment.ground_height,
+        );
+    }
 }
 
 pub fn draw_xyz(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    colors: Res<ColorsConfig>,
 ) {
     // Origin
     commands.spawn(PbrBundle {
@@ -47,13 +66,14 @@ pub fn draw_xyz(
     });
 
     for variant in Axis::iter() {
-        commands.spawn(create_axis(variant, &mut meshes, &mut materials));
+        commands.spawn(create_axis(variant, &colors, &mut meshes, &mut materials));
     }
 }
 
 /// Create an x,y,z axis in the scene
 fn create_axis(
     direction: Axis,
+    colors: &ColorsConfig,
     meshes: &mut ResMut<Assets<Mesh>>,
     materials: &mut ResMut<Assets<StandardMaterial>>,
 ) -> PbrBundle {
@@ -68,17 +88,17 @@ fn create_axis(
         Axis::X => {
             cuboid_dim = Vec3::new(LENGTH, GIRTH, GIRTH);
             adjusted_position = Vec3::new(LENGTH / 2., 0., 0.);
-            color = colors_config::RED;
+            color = colors.x_axis;
         }
         Axis::Y => {
             cuboid_dim = Vec3::new(GIRTH, LENGTH, GIRTH);
             adjusted_position = Vec3::new(0., LENGTH / 2., 0.);
-            color = colors_config::GREEN
+            color = colors.y_axis
         }
         Axis::Z => {
             cuboid_dim = Vec3::new(GIRTH, GIRTH, LENGTH);
             adjusted_position = Vec3::new(0., 0., LENGTH / 2.);
-            color = colors_config::BLUE;
+            color = colors.z_axis;
         }
     }
 
@@ -92,11 +112,12 @@ fn create_axis(
 
 #[derive(Component)]
 pub struct GroundPlane;
-/// Create a ground to help with lighting
+/// Create the ground the nodes collide with at the given height
 fn create_ground(
     commands: &mut Commands,
     meshes: &mut ResMut<Assets<Mesh>>,
     materials: &mut ResMut<Assets<StandardMaterial>>,
+    height: f32,
 ) {
     commands.spawn((
         PbrBundle {
@@ -125,7 +146,7 @@ fn create_ground(
                 ..default()
             }),
             // material: materials.add(Color::Hsla((Hsla::new(0.4, 0.4, 0.4, 1.0)))),
-            transform: Transform::from_xyz(0.0, -4.0, 0.0),
+            transform: Transform::from_xyz(0.0, height, 0.0),
             ..default()
         },
         GroundPlane,
@@ -133,17 +154,17 @@ fn create_ground(
 }
 
 //////////////////////////////////////////////////
-/// CAMERAS
+// CAMERAS
 //////////////////////////////////////////////////
 
 #[derive(Component)]
 struct MyCamera;
 
 /// Create the unreal engine camera object in the scene
-fn create_cameras(commands: &mut Commands) {
-    const STARTING_CAM_POS: Vec3 = cam_config::POS;
+fn create_cameras(commands: &mut Commands, lattice: &LatticeConfig, cam: &CamConfig) {
+    let starting_cam_pos = cam.pos;
 
-    let target = Vec3::splat(lattice_config::STARTING_LINK_LEN * lattice_config::DIM as f32 / 2.);
+    let target = lattice.extent() / 2.;
     println!("Camera is pointing at {}", target);
     let bevy_camera = Camera3dBundle {
         projection: PerspectiveProjection { ..default() }.into(),
@@ -152,13 +173,13 @@ fn create_cameras(commands: &mut Commands) {
 
         // im not sure of the difference of setting here verses setting it with the unreal camera.
         // im not even sure i need both??
-        transform: Transform::from_translation(STARTING_CAM_POS).looking_at(target, Vec3::Y),
+        transform: Transform::from_translation(starting_cam_pos).looking_at(target, Vec3::Y),
         ..default()
     };
 
     let unreal_camera = UnrealCameraBundle::new(
         UnrealCameraController::default(),
-        STARTING_CAM_POS,
+        starting_cam_pos,
         target,
         Vec3::Y,
     );
@@ -176,43 +197,39 @@ pub fn lock_camera(mut camera_controller: Query<&mut UnrealCameraController>) {
 }
 
 //////////////////////////////////////////////////
-/// LIGHTING
+// LIGHTING
 //////////////////////////////////////////////////
 
 // #[derive(Component)]
 // struct MyLight;
 
 /// Create a light in the scene
-fn create_light(commands: &mut Commands, gizmo_store: &mut ResMut<GizmoConfigStore>) {
+fn create_light(commands: &mut Commands, lattice: &LatticeConfig, lights: &LightsConfig) {
     // Light
 
-    let mut new_pos = Transform::from_translation(Vec3::splat(
-        lattice_config::DIM as f32 * lattice_config::STARTING_LINK_LEN
-            + lattice_config::STARTING_LINK_LEN * 3.,
-    ))
-    .looking_at(Vec3::new(5.0, 0.0, 5.0), Vec3::Y);
+    let new_pos =
+        Transform::from_translation(lattice.extent() + Vec3::splat(lattice.starting_link_len * 3.))
+            .looking_at(Vec3::new(5.0, 0.0, 5.0), Vec3::Y);
     // new_pos.translation.z += 5.0;
     // new_pos.translation.x += 5.0;
 
-    // let mut new_pos = Transform::from_xyz(0.0,20.0,0.0).looking_at(Vec3::ZERO, Vec3::Y);
+    // let new_pos = Transform::from_xyz(0.0,20.0,0.0).looking_at(Vec3::ZERO, Vec3::Y);
 
     //TODO: lights are confusing me, they are working backwards as i would expect them to.
     // like placing the light at all postivies values and spawning the camera there, looking at the cube
     // everything is in a shadow.
     let point_light_bundle_1 = SpotLightBundle {
         spot_light: SpotLight {
-            shadows_enabled: lights_config::SPOT_LIGHT_SHADOWS,
+            shadows_enabled: lights.spot_light_shadows,
             shadow_depth_bias: 0.3,
             intensity: 20_000_000.,
             range: 50.,
             color: Color::Srgba(Srgba::WHITE),
             ..default()
         },
-        // transform: Transform::from_translation(lights_config::POS),
-        transform: Transform::from_translation(Vec3::splat(
-            lattice_config::DIM as f32 * lattice_config::STARTING_LINK_LEN
-                + lattice_config::STARTING_LINK_LEN * 2.,
-        ))
+        transform: Transform::from_translation(
+            lattice.extent() + Vec3::splat(lattice.starting_link_len * 2.),
+        )
         .looking_at(Vec3::ZERO, Vec3::Y),
         ..default()
     };
@@ -220,102 +237,55 @@ fn create_light(commands: &mut Commands, gizmo_store: &mut ResMut<GizmoConfigSto
     // Second light
     let point_light_bundle_2 = SpotLightBundle {
         spot_light: SpotLight {
-            shadows_enabled: lights_config::SPOT_LIGHT_SHADOWS,
+            shadows_enabled: lights.spot_light_shadows,
             shadow_depth_bias: 0.3,
             intensity: 20_000_000.,
             range: 50.,
             color: Color::Srgba(Srgba::WHITE),
             ..default()
         },
-        // transform: Transform::from_translation(lights_config::POS_2),
-        transform: Transform::from_translation(Vec3::splat(
-            -1. * lattice_config::DIM as f32 * lattice_config::STARTING_LINK_LEN
-                + lattice_config::STARTING_LINK_LEN * 3.,
-        ))
+        transform: Transform::from_translation(
+            -lattice.extent() + Vec3::splat(lattice.starting_link_len * 3.),
+        )
         .looking_at(Vec3::ZERO, Vec3::Y),
         ..default()
     };
 
     let point_light_bundle_3 = SpotLightBundle {
         spot_light: SpotLight {
-            shadows_enabled: lights_config::SPOT_LIGHT_SHADOWS,
+            shadows_enabled: lights.spot_light_shadows,
             shadow_depth_bias: 0.3,
             intensity: 20_000_000.,
             range: 50.,
             color: Color::Srgba(Srgba::WHITE),
             ..default()
         },
-        // transform: Transform::from_translation(lights_config::POS_2),
         transform: new_pos,
         ..default()
     };
 
-    let mut shadow_behind_light = Transform::from_translation(
-        -1.0 * Vec3::splat(lattice_config::DIM as f32 * lattice_config::STARTING_LINK_LEN - 3.0),
-    );
-    shadow_behind_light.translation.y = shadow_behind_light.translation.y * -1.0 + 6.0;
+    let mut shadow_behind_light =
+        Transform::from_translation(-1.0 * (lattice.extent() - Vec3::splat(3.0)));
+    shadow_behind_light.translation.y = -shadow_behind_light.translation.y + 6.0;
     shadow_behind_light.translation.x += 3.0;
-    shadow_behind_light.look_at(
-        Vec3::splat(lattice_config::DIM as f32 * lattice_config::STARTING_LINK_LEN) / 2.0,
-        Vec3::Y,
-    );
+    shadow_behind_light.look_at(lattice.extent() / 2.0, Vec3::Y);
 
     let point_light_bundle_4 = SpotLightBundle {
         spot_light: SpotLight {
-            shadows_enabled: lights_config::SPOT_LIGHT_SHADOWS,
+            shadows_enabled: lights.spot_light_shadows,
             shadow_depth_bias: 0.3,
             intensity: 30_000_000.,
             range: 100.,
             color: Color::Srgba(Srgba::hex("#FFFFFF").unwrap()),
             ..default()
         },
-        // transform: Transform::from_translation(lights_config::POS_2),
         transform: shadow_behind_light,
         ..default()
     };
 
-    // giant overhead light to give something to the background
-    let xy_pox = 0.0;
-    let camera_height = 30.0;
-    let point_light_bundle_5 = SpotLightBundle {
-        spot_light: SpotLight {
-            shadows_enabled: lights_config::SPOT_LIGHT_SHADOWS,
-            shadow_depth_bias: 0.3,
-            intensity: 100_000_000.,
-            range: 500.,
-            color: Color::Srgba(Srgba::hex("#FFFFFF").unwrap()),
-            ..default()
-        },
-        // transform: Transform::from_translation(lights_config::POS_2),
-        transform: Transform::from_xyz(xy_pox, camera_height, xy_pox)
-            .looking_at(Vec3::new(xy_pox, 0.0, xy_pox), Vec3::Y),
-        ..default()
-    };
-
     // Light spawn
     commands.spawn(point_light_bundle_1);
     commands.spawn(point_light_bundle_2);
     commands.spawn(point_light_bundle_3);
     commands.spawn(point_light_bundle_4);
-    // commands.spawn(point_light_bundle_5);
-
-    // Gimzo config
-    // let (_, light_config) = gizmo_store.config_mut::<LightGizmoConfigGroup>();
-    // light_config.draw_all = true;
-    // light_config.color = LightGizmoColor::Varied;
-}
-
-pub fn animate_ground(
-    material_handles: Query<&Handle<StandardMaterial>, With<GroundPlane>>,
-    time: Res<Time>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-) {
-    let handle = material_handles.single();
-    if let Some(material) = materials.get_mut(handle) {
-        if let Color::Srgba(ref mut srgb) = material.base_color {
-            let mut color = 0.5 * f32::sin(time.elapsed_seconds() * 57.0) + 0.5;
-            color = color.clamp(1.0 / 31.0, 1.0);
-            *srgb = Srgba::rgb(color, color, color);
-        }
-    }
 }